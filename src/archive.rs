@@ -0,0 +1,287 @@
+use crate::content_type::{self, detect_content_type, BZIP2, GZIP, TAR, XZ, ZIP};
+use bzip2::read::BzDecoder;
+use flate2::read::GzDecoder;
+use glob::{MatchOptions, Pattern};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+/*
+Upper bounds for inspecting an archive. Entries are only listed, never extracted, but a tarball has
+to be decompressed to be listed, so an archive over either limit is skipped instead of read to the end.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    pub max_entries: usize,
+    pub max_size: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_size: 4_000_000_000,
+        }
+    }
+}
+
+// Paths of the regular files in an archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveListing {
+    pub files: Vec<String>,
+}
+
+impl ArchiveListing {
+    // Check if any file name in the archive matches a glob, e.g. `*.epub`.
+    pub fn contains(&self, pattern: &Pattern) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        self.files
+            .iter()
+            .any(|file| pattern.matches_with(entry_file_name(file), options))
+    }
+
+    // Check if more than half of the files are of a MIME type such as `image/*`, judging by extension.
+    pub fn dominant_type_is(&self, mime: &str) -> bool {
+        let matching = self
+            .files
+            .iter()
+            .filter(|file| {
+                entry_file_name(file)
+                    .rsplit_once('.')
+                    .and_then(|(_, extension)| content_type::from_extension(extension))
+                    .is_some_and(|content_type| content_type.matches_mime(mime))
+            })
+            .count();
+        matching * 2 > self.files.len()
+    }
+}
+
+/*
+List the files in a zip or tar archive (optionally gzip, xz or bzip2 compressed). Returns None for
+files that are not archives or can't be read, and an error when the archive exceeds the limits.
+*/
+pub fn list_archive<P: AsRef<Path>>(
+    path: P,
+    limits: &ArchiveLimits,
+) -> Result<Option<ArchiveListing>, String> {
+    let path = path.as_ref();
+    let content_type = match detect_content_type(path) {
+        Ok(Some(content_type)) => content_type,
+        _ => return Ok(None),
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    let files = if content_type == ZIP {
+        list_zip(file, limits)?
+    } else if content_type == TAR {
+        list_tar(file, limits)?
+    } else if content_type == GZIP {
+        list_tar(GzDecoder::new(file), limits)?
+    } else if content_type == XZ {
+        list_tar(XzDecoder::new(file), limits)?
+    } else if content_type == BZIP2 {
+        list_tar(BzDecoder::new(file), limits)?
+    } else {
+        None
+    };
+
+    Ok(files.map(|files| ArchiveListing {
+        files: files
+            .into_iter()
+            .filter(|file| !is_metadata_entry(file))
+            .collect(),
+    }))
+}
+
+// Running totals checked against the limits while entries are listed.
+struct Tally<'a> {
+    limits: &'a ArchiveLimits,
+    entries: usize,
+    size: u64,
+}
+
+impl<'a> Tally<'a> {
+    fn new(limits: &'a ArchiveLimits) -> Self {
+        Self {
+            limits,
+            entries: 0,
+            size: 0,
+        }
+    }
+
+    fn add(&mut self, size: u64) -> Result<(), String> {
+        self.entries += 1;
+        self.size = self.size.saturating_add(size);
+        if self.entries > self.limits.max_entries {
+            return Err(format!(
+                "more than {} entries in archive",
+                self.limits.max_entries
+            ));
+        }
+        if self.size > self.limits.max_size {
+            return Err(format!(
+                "more than {} bytes uncompressed in archive",
+                self.limits.max_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The zip central directory has names and sizes of all entries, so nothing is decompressed.
+fn list_zip(file: File, limits: &ArchiveLimits) -> Result<Option<Vec<String>>, String> {
+    let mut archive = match zip::ZipArchive::new(file) {
+        Ok(archive) => archive,
+        Err(_) => return Ok(None),
+    };
+    if archive.len() > limits.max_entries {
+        return Err(format!(
+            "{} entries in archive, the limit is {}",
+            archive.len(),
+            limits.max_entries
+        ));
+    }
+
+    let mut tally = Tally::new(limits);
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let entry = match archive.by_index_raw(index) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+        tally.add(entry.size())?;
+        if !entry.is_dir() {
+            files.push(entry.name().to_string());
+        }
+    }
+    Ok(Some(files))
+}
+
+// Tar has no index, so the stream is read through; entry data is skipped, not kept.
+fn list_tar<R: Read>(reader: R, limits: &ArchiveLimits) -> Result<Option<Vec<String>>, String> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(_) => return Ok(None),
+    };
+
+    let mut tally = Tally::new(limits);
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Not a tarball after all, e.g. a single gzipped log file.
+            Err(_) => return Ok(None),
+        };
+        tally.add(entry.size())?;
+        if entry.header().entry_type().is_file() {
+            if let Ok(path) = entry.path() {
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(Some(files))
+}
+
+fn entry_file_name(entry: &str) -> &str {
+    entry.rsplit('/').next().unwrap_or(entry)
+}
+
+// Finder adds `__MACOSX/` resource forks and `.DS_Store` files that shouldn't count towards the content.
+fn is_metadata_entry(entry: &str) -> bool {
+    entry.starts_with("__MACOSX/") || entry_file_name(entry).starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_zip(path: &Path, names: &[&str]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for name in names {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(b"content").unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_list_zip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("holiday.zip");
+        write_zip(
+            &path,
+            &[
+                "IMG_0001.JPG",
+                "IMG_0002.png",
+                "notes.txt",
+                "__MACOSX/._IMG_0001.JPG",
+            ],
+        );
+
+        let listing = list_archive(&path, &ArchiveLimits::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(listing.files.len(), 3);
+        assert!(listing.dominant_type_is("image/*"));
+        assert!(!listing.dominant_type_is("image/png"));
+        assert!(listing.contains(&Pattern::new("*.txt").unwrap()));
+        assert!(!listing.contains(&Pattern::new("*.epub").unwrap()));
+
+        let limits = ArchiveLimits {
+            max_entries: 2,
+            ..ArchiveLimits::default()
+        };
+        assert!(list_archive(&path, &limits).is_err());
+    }
+
+    #[test]
+    fn test_list_tar_gz() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("books.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for name in ["books/one.epub", "books/two.epub"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1000);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, &[0u8; 1000][..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let listing = list_archive(&path, &ArchiveLimits::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(listing.files, vec!["books/one.epub", "books/two.epub"]);
+        assert!(listing.dominant_type_is("application/epub+zip"));
+
+        let limits = ArchiveLimits {
+            max_size: 1500,
+            ..ArchiveLimits::default()
+        };
+        assert!(list_archive(&path, &limits).is_err());
+    }
+
+    #[test]
+    fn test_list_archive_ignores_other_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "plain text").unwrap();
+        assert_eq!(list_archive(&path, &ArchiveLimits::default()), Ok(None));
+    }
+}
@@ -0,0 +1,537 @@
+use crate::content_type::detect_content_type;
+use crate::file_utils::{parse_duration, parse_size};
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/*
+A rule condition such as `ext in [jpg, png] and size > 5MB and not name ~ "^Screenshot"`.
+
+Conditions combine comparisons with `and`, `or`, `not` and parentheses. The fields are `name` (the
+full file name), `stem`, `ext`, `mime` (detected from the content), `size` and `age` (since the last
+modification). Text fields compare case-insensitively with `==`, `!=` and `in [...]`, or with a
+regular expression using `~` and `!~`. `size` and `age` take `==`, `!=`, `<`, `<=`, `>` and `>=`
+with values like `5MB` and `7d`. Values are bare words or double-quoted strings, in which `\"` and
+`\\` are the only escapes.
+*/
+#[derive(Debug)]
+pub enum Condition {
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(Comparison),
+}
+
+#[derive(Debug)]
+pub enum Comparison {
+    Text {
+        field: TextField,
+        values: Vec<String>,
+        negated: bool,
+    },
+    Matches {
+        field: TextField,
+        regex: Regex,
+        negated: bool,
+    },
+    Size(Order, u64),
+    Age(Order, Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    Name,
+    Stem,
+    Ext,
+    Mime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Order {
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Order::Equal => left == right,
+            Order::NotEqual => left != right,
+            Order::Less => left < right,
+            Order::LessOrEqual => left <= right,
+            Order::Greater => left > right,
+            Order::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+// A parse error with the 1-based column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ConditionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            position: 0,
+        };
+        let condition = parser.or()?;
+        match parser.peek() {
+            None => Ok(condition),
+            Some(token) => Err(parser.error_at(token, "expected `and`, `or` or the end")),
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        match self {
+            Condition::And(left, right) => left.matches(path) && right.matches(path),
+            Condition::Or(left, right) => left.matches(path) || right.matches(path),
+            Condition::Not(inner) => !inner.matches(path),
+            Condition::Compare(comparison) => comparison.matches(path),
+        }
+    }
+}
+
+impl Comparison {
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Comparison::Text {
+                field,
+                values,
+                negated,
+            } => values.iter().any(|value| text_equals(*field, path, value)) != *negated,
+            Comparison::Matches {
+                field,
+                regex,
+                negated,
+            } => text_value(*field, path).is_some_and(|text| regex.is_match(&text)) != *negated,
+            Comparison::Size(order, bytes) => fs::metadata(path)
+                .map(|metadata| order.holds(metadata.len(), *bytes))
+                .unwrap_or(false),
+            Comparison::Age(order, duration) => fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| {
+                    let age = SystemTime::now()
+                        .duration_since(modified)
+                        .unwrap_or_default();
+                    order.holds(age, *duration)
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+fn text_value(field: TextField, path: &Path) -> Option<String> {
+    let value = match field {
+        TextField::Name => path.file_name()?.to_str()?.to_string(),
+        TextField::Stem => path.file_stem()?.to_str()?.to_string(),
+        TextField::Ext => path.extension()?.to_str()?.to_string(),
+        TextField::Mime => detect_content_type(path).ok()??.mime.to_string(),
+    };
+    Some(value)
+}
+
+fn text_equals(field: TextField, path: &Path, value: &str) -> bool {
+    match field {
+        // Compare the trailing suffix so compound extensions like `tar.gz` work too.
+        TextField::Ext => path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                name.to_lowercase()
+                    .ends_with(&format!(".{}", value.to_lowercase()))
+            }),
+        TextField::Mime => detect_content_type(path)
+            .ok()
+            .flatten()
+            .is_some_and(|content_type| content_type.matches_mime(value)),
+        _ => text_value(field, path).is_some_and(|text| text.eq_ignore_ascii_case(value)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Quoted(String),
+    Operator(&'static str),
+    Punctuation(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+}
+
+const OPERATORS: [&str; 8] = ["==", "!=", "!~", "<=", ">=", "<", ">", "~"];
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '/' | '*' | '+')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if matches!(c, '(' | ')' | '[' | ']' | ',') {
+            chars.next();
+            tokens.push(Token {
+                kind: TokenKind::Punctuation(c),
+                offset,
+            });
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    // Only quotes and backslashes are escaped, so regex escapes like `\d` survive.
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                        Some((_, escaped)) => {
+                            value.push('\\');
+                            value.push(escaped);
+                        }
+                        None => break,
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        return Err(ConditionError {
+                            column: column(source, offset),
+                            message: "unterminated string".to_string(),
+                        })
+                    }
+                }
+            }
+            tokens.push(Token {
+                kind: TokenKind::Quoted(value),
+                offset,
+            });
+        } else if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| source[offset..].starts_with(*operator))
+        {
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            tokens.push(Token {
+                kind: TokenKind::Operator(operator),
+                offset,
+            });
+        } else if is_word_char(c) {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !is_word_char(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token {
+                kind: TokenKind::Word(word),
+                offset,
+            });
+        } else {
+            return Err(ConditionError {
+                column: column(source, offset),
+                message: format!("unexpected character '{}'", c),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn column(source: &str, offset: usize) -> usize {
+    source[..offset].chars().count() + 1
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn error_at(&self, token: &Token, message: &str) -> ConditionError {
+        ConditionError {
+            column: column(self.source, token.offset),
+            message: message.to_string(),
+        }
+    }
+
+    // Errors at the end point just past the last character.
+    fn error_at_end(&self, message: &str) -> ConditionError {
+        ConditionError {
+            column: self.source.chars().count() + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect_next(&mut self, expected: &str) -> Result<Token, ConditionError> {
+        self.next()
+            .ok_or_else(|| self.error_at_end(&format!("expected {}", expected)))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.peek(),
+            Some(Token { kind: TokenKind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Condition, ConditionError> {
+        let mut condition = self.and()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, ConditionError> {
+        let mut condition = self.unary()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, ConditionError> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if matches!(
+            self.peek(),
+            Some(Token {
+                kind: TokenKind::Punctuation('('),
+                ..
+            })
+        ) {
+            let open = self.next().unwrap();
+            let condition = self.or()?;
+            return match self.next() {
+                Some(Token {
+                    kind: TokenKind::Punctuation(')'),
+                    ..
+                }) => Ok(condition),
+                Some(token) => Err(self.error_at(&token, "expected `)`")),
+                None => Err(self.error_at(&open, "unclosed `(`")),
+            };
+        }
+        self.comparison().map(Condition::Compare)
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, ConditionError> {
+        let field_token = self.expect_next("a field")?;
+        let field = match &field_token.kind {
+            TokenKind::Word(word) => word.to_lowercase(),
+            _ => return Err(self.error_at(&field_token, "expected a field")),
+        };
+        let operator_token = self.expect_next("an operator")?;
+        let operator = match &operator_token.kind {
+            TokenKind::Operator(operator) => *operator,
+            TokenKind::Word(word) if word.eq_ignore_ascii_case("in") => "in",
+            _ => return Err(self.error_at(&operator_token, "expected an operator")),
+        };
+
+        match field.as_str() {
+            "size" | "age" => {
+                let order = match operator {
+                    "==" => Order::Equal,
+                    "!=" => Order::NotEqual,
+                    "<" => Order::Less,
+                    "<=" => Order::LessOrEqual,
+                    ">" => Order::Greater,
+                    ">=" => Order::GreaterOrEqual,
+                    _ => {
+                        return Err(self.error_at(
+                            &operator_token,
+                            &format!("`{}` can't be used with {}", operator, field),
+                        ))
+                    }
+                };
+                let (value_token, value) = self.value()?;
+                if field == "size" {
+                    let bytes = parse_size(&value).ok_or_else(|| {
+                        self.error_at(&value_token, &format!("invalid size '{}'", value))
+                    })?;
+                    Ok(Comparison::Size(order, bytes))
+                } else {
+                    let duration = parse_duration(&value).ok_or_else(|| {
+                        self.error_at(&value_token, &format!("invalid duration '{}'", value))
+                    })?;
+                    Ok(Comparison::Age(order, duration))
+                }
+            }
+            "name" | "stem" | "ext" | "mime" => {
+                let field = match field.as_str() {
+                    "name" => TextField::Name,
+                    "stem" => TextField::Stem,
+                    "ext" => TextField::Ext,
+                    _ => TextField::Mime,
+                };
+                match operator {
+                    "==" | "!=" => Ok(Comparison::Text {
+                        field,
+                        values: vec![self.value()?.1],
+                        negated: operator == "!=",
+                    }),
+                    "in" => Ok(Comparison::Text {
+                        field,
+                        values: self.list()?,
+                        negated: false,
+                    }),
+                    "~" | "!~" => {
+                        let (value_token, pattern) = self.value()?;
+                        let regex = Regex::new(&pattern).map_err(|e| {
+                            self.error_at(&value_token, &format!("invalid regex: {}", e))
+                        })?;
+                        Ok(Comparison::Matches {
+                            field,
+                            regex,
+                            negated: operator == "!~",
+                        })
+                    }
+                    _ => Err(self.error_at(
+                        &operator_token,
+                        &format!("`{}` can't be used with {}", operator, field_name(field)),
+                    )),
+                }
+            }
+            _ => Err(self.error_at(
+                &field_token,
+                &format!(
+                    "unknown field '{}', expected name, stem, ext, mime, size or age",
+                    field
+                ),
+            )),
+        }
+    }
+
+    fn value(&mut self) -> Result<(Token, String), ConditionError> {
+        let token = self.expect_next("a value")?;
+        match &token.kind {
+            TokenKind::Word(value) | TokenKind::Quoted(value) => {
+                let value = value.clone();
+                Ok((token, value))
+            }
+            _ => Err(self.error_at(&token, "expected a value")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<String>, ConditionError> {
+        let open = self.expect_next("`[`")?;
+        if open.kind != TokenKind::Punctuation('[') {
+            return Err(self.error_at(&open, "expected `[`"));
+        }
+        let mut values = Vec::new();
+        loop {
+            values.push(self.value()?.1);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Punctuation(','),
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::Punctuation(']'),
+                    ..
+                }) => return Ok(values),
+                Some(token) => return Err(self.error_at(&token, "expected `,` or `]`")),
+                None => return Err(self.error_at(&open, "unclosed `[`")),
+            }
+        }
+    }
+}
+
+fn field_name(field: TextField) -> &'static str {
+    match field {
+        TextField::Name => "name",
+        TextField::Stem => "stem",
+        TextField::Ext => "ext",
+        TextField::Mime => "mime",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_matches() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let photo = temp_dir.path().join("IMG_0001.JPG");
+        fs::write(&photo, vec![0u8; 2000]).unwrap();
+        let screenshot = temp_dir.path().join("Screenshot 2024-04-01.png");
+        fs::write(&screenshot, vec![0u8; 2000]).unwrap();
+        let backup = temp_dir.path().join("site.tar.gz");
+        fs::write(&backup, "small").unwrap();
+
+        let condition = Condition::parse(
+            r#"ext in [jpg, png] and size > 1KB and not name ~ "^Screenshot \d{4}""#,
+        )
+        .unwrap();
+        assert!(condition.matches(&photo));
+        assert!(!condition.matches(&screenshot));
+        assert!(!condition.matches(&backup));
+
+        let condition =
+            Condition::parse("(ext == tar.gz or stem == IMG_0001) and age < 1d").unwrap();
+        assert!(condition.matches(&backup));
+        assert!(condition.matches(&photo));
+        assert!(!condition.matches(&screenshot));
+    }
+
+    #[test]
+    fn test_condition_errors() {
+        let error = |source: &str| Condition::parse(source).unwrap_err().to_string();
+
+        assert_eq!(
+            error("ext in [jpg, png] and size > 5XB"),
+            "column 30: invalid size '5XB'"
+        );
+        assert_eq!(
+            error("colour == red"),
+            "column 1: unknown field 'colour', expected name, stem, ext, mime, size or age"
+        );
+        assert!(error("name ~ \"(\"").starts_with("column 8: invalid regex"));
+        assert_eq!(error("size ~ 5MB"), "column 6: `~` can't be used with size");
+        assert_eq!(error("(ext == pdf"), "column 1: unclosed `(`");
+        assert_eq!(
+            error("ext == pdf size"),
+            "column 12: expected `and`, `or` or the end"
+        );
+        assert_eq!(error("ext =="), "column 7: expected a value");
+    }
+}
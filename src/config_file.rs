@@ -0,0 +1,578 @@
+use crate::error::FileRouterError;
+use crate::file_utils::{parse_duration, parse_size};
+use crate::ignore::IgnoreRules;
+use crate::path_expand::expand_path;
+use crate::path_template::validate_template;
+use crate::{MatchMode, RuleAction, TimestampKind};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::Spanned;
+
+/*
+Per-directory options that sit next to the directory keys, e.g. `downloads_enabled`.
+*/
+const DIRECTORY_OPTION_SUFFIXES: [&str; 5] = [
+    "_enabled",
+    "_exclude",
+    "_quiet_period",
+    "_unsorted",
+    "_unsorted_after",
+];
+
+/*
+The config file as written, before paths are expanded and patterns are compiled. Unknown keys are
+rejected, and values that are checked after parsing keep their position for error messages.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    // Paths or glob patterns of more config files, see `ConfigFile::read_includes`.
+    #[serde(default)]
+    pub include: Vec<Spanned<String>>,
+    #[serde(default)]
+    pub directories: DirectoryTable,
+    #[serde(default)]
+    pub extensions: Vec<Spanned<RuleFile>>,
+    #[serde(default)]
+    pub match_mode: MatchMode,
+    pub exclude: Option<Spanned<Vec<String>>>,
+    pub temporary_files: Option<Spanned<Vec<String>>>,
+    pub quiet_period: Option<ConfigDuration>,
+    pub archive_max_entries: Option<usize>,
+    pub archive_max_size: Option<ConfigSize>,
+}
+
+// An included config file, which can only add directories and rules.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FragmentFile {
+    #[serde(default)]
+    pub directories: DirectoryTable,
+    #[serde(default)]
+    pub extensions: Vec<Spanned<RuleFile>>,
+}
+
+// Directories and their options in one flat table, see `merge_directories`.
+pub type DirectoryTable = BTreeMap<Spanned<String>, Spanned<toml::Value>>;
+
+// One `[[extensions]]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleFile {
+    #[serde(default)]
+    pub enabled: bool,
+    pub rule: Option<String>,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub sources: Vec<Spanned<String>>,
+    pub name: Option<String>,
+    pub path: Option<Spanned<String>>,
+    #[serde(default)]
+    pub action: RuleAction,
+    pub rename: Option<Spanned<String>>,
+    pub glob: Option<Spanned<String>>,
+    pub regex: Option<Spanned<String>>,
+    pub when: Option<Spanned<String>>,
+    #[serde(default)]
+    pub no_extension: bool,
+    pub mime: Option<String>,
+    #[serde(default)]
+    pub fix_extension: bool,
+    pub min_size: Option<ConfigSize>,
+    pub max_size: Option<ConfigSize>,
+    pub older_than: Option<ConfigDuration>,
+    pub newer_than: Option<ConfigDuration>,
+    #[serde(default)]
+    pub timestamp: TimestampKind,
+    pub camera: Option<Spanned<String>>,
+    pub archive_type: Option<String>,
+    pub archive_contains: Option<Spanned<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub text_contains: Vec<String>,
+    pub text_regex: Option<Spanned<String>>,
+    pub text_scan_limit: Option<ConfigSize>,
+    pub origin_host: Option<String>,
+    pub origin_url: Option<Spanned<String>>,
+    pub run_before: Option<String>,
+    pub run_after: Option<String>,
+    pub run_timeout: Option<ConfigDuration>,
+    #[serde(default)]
+    pub strip_copy_suffix: bool,
+    #[serde(default)]
+    pub slugify: bool,
+    #[serde(default)]
+    pub lowercase_extension: bool,
+    #[serde(default)]
+    pub normalize_unicode: bool,
+}
+
+// A watched directory with the options found next to it in `[directories]`.
+#[derive(Debug, Default)]
+pub struct DirectoryFile {
+    pub path: String,
+    pub enabled: bool,
+    pub exclude: Option<IgnoreRules>,
+    pub quiet_period: Option<Duration>,
+    pub unsorted: Option<String>,
+    pub unsorted_after: Option<Duration>,
+}
+
+// A size in bytes, written as a byte count or as a string with units such as `500MB`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigSize(pub u64);
+
+impl<'de> Deserialize<'de> for ConfigSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SizeVisitor;
+
+        impl Visitor<'_> for SizeVisitor {
+            type Value = ConfigSize;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a byte count or a size such as \"500MB\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, bytes: i64) -> Result<ConfigSize, E> {
+                u64::try_from(bytes)
+                    .map(ConfigSize)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(bytes), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, bytes: u64) -> Result<ConfigSize, E> {
+                Ok(ConfigSize(bytes))
+            }
+
+            fn visit_str<E: de::Error>(self, size: &str) -> Result<ConfigSize, E> {
+                parse_size(size)
+                    .map(ConfigSize)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(size), &self))
+            }
+        }
+
+        deserializer.deserialize_any(SizeVisitor)
+    }
+}
+
+// A duration written as a string such as `30d`, `1w2d` or `90` (seconds).
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigDuration(pub Duration);
+
+impl<'de> Deserialize<'de> for ConfigDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DurationVisitor;
+
+        impl Visitor<'_> for DurationVisitor {
+            type Value = ConfigDuration;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a duration such as \"30d\" or \"1h30m\"")
+            }
+
+            fn visit_str<E: de::Error>(self, duration: &str) -> Result<ConfigDuration, E> {
+                parse_duration(duration)
+                    .map(ConfigDuration)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(duration), &self))
+            }
+        }
+
+        deserializer.deserialize_str(DurationVisitor)
+    }
+}
+
+// Accept a single string or a list of strings.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    struct OneOrManyVisitor;
+
+    impl<'de> Visitor<'de> for OneOrManyVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string or a list of strings")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Vec<String>, E> {
+            Ok(vec![value.to_string()])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<String>, A::Error> {
+            let mut values = Vec::new();
+            while let Some(value) = seq.next_element()? {
+                values.push(value);
+            }
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_any(OneOrManyVisitor)
+}
+
+/*
+The text of a config file, kept around so errors can point at the line and column of the key or
+value they are about.
+*/
+#[derive(Debug)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    content: String,
+}
+
+impl ConfigSource {
+    pub fn read(path: &Path) -> Result<Self, FileRouterError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            FileRouterError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(path, content))
+    }
+
+    pub fn new(path: &Path, content: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            content,
+        }
+    }
+
+    pub fn parse(&self) -> Result<ConfigFile, FileRouterError> {
+        self.deserialize()
+    }
+
+    pub fn parse_fragment(&self) -> Result<FragmentFile, FileRouterError> {
+        self.deserialize()
+    }
+
+    fn deserialize<T: de::DeserializeOwned>(&self) -> Result<T, FileRouterError> {
+        toml::from_str(&self.content).map_err(|e| match e.span() {
+            Some(span) => {
+                let key = self.key_at(span.start);
+                self.error(span, &key, e.message())
+            }
+            None => {
+                FileRouterError::ConfigError(format!("{}: {}", self.path.display(), e.message()))
+            }
+        })
+    }
+
+    // An error about the key or value at `span`, as `file:line:column: key: message`.
+    pub fn error<M: fmt::Display>(
+        &self,
+        span: Range<usize>,
+        key: &str,
+        message: M,
+    ) -> FileRouterError {
+        let (line, column) = self.position(span.start);
+        let location = format!("{}:{}:{}", self.path.display(), line, column);
+        if key.is_empty() {
+            FileRouterError::ConfigError(format!("{}: {}", location, message))
+        } else {
+            FileRouterError::ConfigError(format!("{}: {}: {}", location, key, message))
+        }
+    }
+
+    // One-based line and column of a byte offset.
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.content[..offset.min(self.content.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    // The key assigned on the line of an offset, or the table when the line is a header.
+    fn key_at(&self, offset: usize) -> String {
+        let offset = offset.min(self.content.len());
+        let line_start = self.content[..offset]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        let line = self.content[line_start..]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim();
+        if line.starts_with('[') {
+            line.trim_matches(|c| c == '[' || c == ']')
+                .trim()
+                .to_string()
+        } else {
+            line.split_once('=')
+                .map(|(key, _)| key.trim().trim_matches('"').to_string())
+                .unwrap_or_default()
+        }
+    }
+}
+
+impl ConfigFile {
+    /*
+    Read the files listed in `include`, in the order they are listed. Relative paths are resolved
+    against the directory of this file, and the files matching a pattern are read in alphabetical
+    order. A pattern matching nothing is fine, a plain path that doesn't exist is an error.
+    */
+    pub fn read_includes(
+        &self,
+        source: &ConfigSource,
+    ) -> Result<Vec<(ConfigSource, FragmentFile)>, FileRouterError> {
+        let mut fragments: Vec<(ConfigSource, FragmentFile)> = Vec::new();
+        let patterns = self.include_patterns(source)?;
+        for (include, pattern) in self.include.iter().zip(patterns) {
+            let paths = glob::glob(pattern.as_str())
+                .map_err(|e| source.error(include.span(), "include", e))?
+                .filter_map(Result::ok)
+                .collect::<Vec<_>>();
+            if paths.is_empty() && !is_glob(include.get_ref()) {
+                return Err(source.error(
+                    include.span(),
+                    "include",
+                    format!("Included file {} does not exist", pattern.as_str()),
+                ));
+            }
+
+            for path in paths {
+                if path == source.path
+                    || fragments.iter().any(|(fragment, _)| fragment.path == path)
+                {
+                    continue;
+                }
+                let fragment_source = ConfigSource::read(&path)?;
+                let fragment = fragment_source.parse_fragment()?;
+                fragments.push((fragment_source, fragment));
+            }
+        }
+        Ok(fragments)
+    }
+
+    // The `include` entries as absolute patterns, to read them and to spot changes to them.
+    pub fn include_patterns(
+        &self,
+        source: &ConfigSource,
+    ) -> Result<Vec<glob::Pattern>, FileRouterError> {
+        let base = source.path.parent().unwrap_or(Path::new(""));
+        self.include
+            .iter()
+            .map(|include| {
+                let path = expand_path(include.get_ref())
+                    .map_err(|e| source.error(include.span(), "include", e))?;
+                glob::Pattern::new(&base.join(path).to_string_lossy())
+                    .map_err(|e| source.error(include.span(), "include", e))
+            })
+            .collect()
+    }
+}
+
+/*
+Group the flat `[directories]` tables of a config and its includes by directory. Later files
+override the keys of earlier ones. A key ending in one of the option suffixes belongs to the
+directory it starts with, which has to be listed in one of the files; any other key is a directory.
+*/
+pub fn merge_directories<'a, I>(
+    tables: I,
+) -> Result<BTreeMap<String, DirectoryFile>, FileRouterError>
+where
+    I: IntoIterator<Item = (&'a ConfigSource, &'a DirectoryTable)>,
+{
+    let mut entries = BTreeMap::new();
+    for (source, table) in tables {
+        for (key, value) in table {
+            entries.insert(key.get_ref().as_str(), (source, key, value));
+        }
+    }
+
+    let mut directories = BTreeMap::new();
+    for (source, key, value) in entries.values() {
+        if directory_option(key.get_ref()).is_none() {
+            let path: String = directory_value(source, key, value)?;
+            let path =
+                expand_path(&path).map_err(|e| source.error(value.span(), key.get_ref(), e))?;
+            directories.insert(
+                key.get_ref().clone(),
+                DirectoryFile {
+                    path,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    for (source, key, value) in entries.values() {
+        let (name, suffix) = match directory_option(key.get_ref()) {
+            Some(option) => option,
+            None => continue,
+        };
+        let directory = directories.get_mut(name).ok_or_else(|| {
+            source.error(
+                key.span(),
+                key.get_ref(),
+                format!("no directory named {} in [directories]", name),
+            )
+        })?;
+        match suffix {
+            "_enabled" => directory.enabled = directory_value(source, key, value)?,
+            "_exclude" => {
+                let patterns: Vec<String> = directory_value(source, key, value)?;
+                directory.exclude = Some(
+                    IgnoreRules::parse(patterns.iter().map(|pattern| pattern.as_str()))
+                        .map_err(|e| source.error(value.span(), key.get_ref(), e))?,
+                );
+            }
+            "_quiet_period" => {
+                let ConfigDuration(quiet_period) = directory_value(source, key, value)?;
+                directory.quiet_period = Some(quiet_period);
+            }
+            "_unsorted" => {
+                let unsorted: String = directory_value(source, key, value)?;
+                let unsorted = expand_path(&unsorted)
+                    .map_err(|e| source.error(value.span(), key.get_ref(), e))?;
+                validate_template(&unsorted)
+                    .map_err(|e| source.error(value.span(), key.get_ref(), e))?;
+                directory.unsorted = Some(unsorted);
+            }
+            _ => {
+                let ConfigDuration(unsorted_after) = directory_value(source, key, value)?;
+                directory.unsorted_after = Some(unsorted_after);
+            }
+        }
+    }
+    Ok(directories)
+}
+
+// Split `downloads_enabled` into the directory and the option suffix.
+fn directory_option(key: &str) -> Option<(&str, &'static str)> {
+    DIRECTORY_OPTION_SUFFIXES
+        .iter()
+        .find_map(|suffix| key.strip_suffix(suffix).map(|name| (name, *suffix)))
+}
+
+// The directory an include pattern reads from, which is watched for new and changed files.
+pub fn include_dir(pattern: &glob::Pattern) -> PathBuf {
+    let path = Path::new(pattern.as_str());
+    let mut dir = PathBuf::new();
+    for component in path.components() {
+        if is_glob(&component.as_os_str().to_string_lossy()) {
+            return dir;
+        }
+        dir.push(component);
+    }
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+// Check if a path has glob wildcards, rather than naming a single file.
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+fn directory_value<T: de::DeserializeOwned>(
+    source: &ConfigSource,
+    key: &Spanned<String>,
+    value: &Spanned<toml::Value>,
+) -> Result<T, FileRouterError> {
+    value
+        .get_ref()
+        .clone()
+        .try_into()
+        .map_err(|e: toml::de::Error| source.error(value.span(), key.get_ref(), e.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<ConfigFile, String> {
+        ConfigSource::new(Path::new("config.toml"), content.to_string())
+            .parse()
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_load_config() {
+        let content = r#"
+[directories]
+downloads = "test_downloads"
+
+[[extensions]]
+name = "txt"
+path = "test_downloads/TXT"
+enabled = true
+"#;
+        let source = ConfigSource::new(Path::new("config.toml"), content.to_string());
+        let config = source.parse().unwrap();
+        let directories = merge_directories([(&source, &config.directories)]).unwrap();
+        assert_eq!(directories["downloads"].path, "test_downloads");
+        assert!(!directories["downloads"].enabled);
+        assert_eq!(config.extensions.len(), 1);
+        assert_eq!(config.extensions[0].get_ref().name.as_deref(), Some("txt"));
+        assert_eq!(
+            config.extensions[0]
+                .get_ref()
+                .path
+                .as_ref()
+                .unwrap()
+                .get_ref(),
+            "test_downloads/TXT"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_key() {
+        let error = parse("[directories]\n\n[[extensions]]\nname = \"pdf\"\n  globb = \"a*\"\n")
+            .unwrap_err();
+        assert!(
+            error.starts_with("Config Error: config.toml:5:3: globb: unknown field `globb`"),
+            "{}",
+            error
+        );
+
+        let error = parse("[directories]\n[[extensions]]\nenabled = \"yes\"\n").unwrap_err();
+        assert!(
+            error.starts_with("Config Error: config.toml:3:11: enabled: invalid type"),
+            "{}",
+            error
+        );
+
+        let error = parse("[[extensions]]\nname = \"iso\"\nmax_size = \"5XB\"\n").unwrap_err();
+        assert!(
+            error.contains("config.toml:3:12: max_size: invalid value"),
+            "{}",
+            error
+        );
+
+        let error = parse("[[extensions]]\naction = \"shred\"\n").unwrap_err();
+        assert!(error.contains("unknown variant `shred`"), "{}", error);
+
+        // Neither table is required.
+        assert!(parse("match_mode = \"all\"\n").is_ok());
+    }
+
+    #[test]
+    fn test_directory_options() {
+        let content = r#"[directories]
+downloads = "~/Downloads"
+downloads_enabled = true
+downloads_quiet_period = "2m"
+downlaods_exclude = ["*.part"]
+"#;
+        let source = ConfigSource::new(Path::new("config.toml"), content.to_string());
+        let error = merge_directories([(&source, &source.parse().unwrap().directories)])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Config Error: config.toml:5:1: downlaods_exclude: no directory named downlaods in [directories]"
+        );
+
+        let source = ConfigSource::new(
+            Path::new("config.toml"),
+            content.replace("downlaods", "downloads"),
+        );
+        let directories =
+            merge_directories([(&source, &source.parse().unwrap().directories)]).unwrap();
+        let downloads = &directories["downloads"];
+        assert!(downloads.enabled);
+        assert!(downloads.exclude.is_some());
+        assert_eq!(downloads.quiet_period, Some(Duration::from_secs(120)));
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/*
+Number of leading bytes read from a file. ISO9660 images keep their signature at offset 0x8001,
+so the buffer has to reach past it.
+*/
+const SNIFF_LEN: u64 = 0x8006;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentType {
+    pub mime: &'static str,
+    // Accepted extensions for this type, the first one is used when fixing up a file name.
+    pub extensions: &'static [&'static str],
+}
+
+impl ContentType {
+    const fn new(mime: &'static str, extensions: &'static [&'static str]) -> Self {
+        Self { mime, extensions }
+    }
+
+    pub fn canonical_extension(&self) -> &'static str {
+        self.extensions[0]
+    }
+
+    // Check if an extension is one of the accepted extensions for this type.
+    pub fn accepts_extension(&self, extension: &str) -> bool {
+        self.extensions
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension))
+    }
+
+    // Match a configured MIME pattern such as `application/pdf` or `image/*`.
+    pub fn matches_mime(&self, pattern: &str) -> bool {
+        match pattern.strip_suffix("/*") {
+            Some(top_level) => self
+                .mime
+                .split_once('/')
+                .is_some_and(|(mime_top, _)| mime_top.eq_ignore_ascii_case(top_level)),
+            None => self.mime.eq_ignore_ascii_case(pattern),
+        }
+    }
+}
+
+const PDF: ContentType = ContentType::new("application/pdf", &["pdf"]);
+const PNG: ContentType = ContentType::new("image/png", &["png"]);
+const JPEG: ContentType = ContentType::new("image/jpeg", &["jpg", "jpeg", "jpe"]);
+const GIF: ContentType = ContentType::new("image/gif", &["gif"]);
+const TIFF: ContentType = ContentType::new("image/tiff", &["tif", "tiff"]);
+const WEBP: ContentType = ContentType::new("image/webp", &["webp"]);
+const HEIC: ContentType = ContentType::new("image/heic", &["heic", "heif"]);
+const AVIF: ContentType = ContentType::new("image/avif", &["avif"]);
+pub const ZIP: ContentType = ContentType::new("application/zip", &["zip"]);
+const EPUB: ContentType = ContentType::new("application/epub+zip", &["epub"]);
+const DOCX: ContentType = ContentType::new(
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    &["docx"],
+);
+const XLSX: ContentType = ContentType::new(
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    &["xlsx"],
+);
+const PPTX: ContentType = ContentType::new(
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    &["pptx"],
+);
+pub const GZIP: ContentType = ContentType::new("application/gzip", &["gz", "tgz"]);
+pub const XZ: ContentType = ContentType::new("application/x-xz", &["xz", "txz"]);
+pub const BZIP2: ContentType = ContentType::new("application/x-bzip2", &["bz2", "tbz2"]);
+const ZSTD: ContentType = ContentType::new("application/zstd", &["zst"]);
+const SEVEN_ZIP: ContentType = ContentType::new("application/x-7z-compressed", &["7z"]);
+pub const TAR: ContentType = ContentType::new("application/x-tar", &["tar"]);
+const ISO: ContentType = ContentType::new("application/x-iso9660-image", &["iso"]);
+const MP4: ContentType = ContentType::new("video/mp4", &["mp4", "m4v"]);
+const M4A: ContentType = ContentType::new("audio/mp4", &["m4a"]);
+const QUICKTIME: ContentType = ContentType::new("video/quicktime", &["mov"]);
+const MATROSKA: ContentType = ContentType::new("video/x-matroska", &["mkv", "webm"]);
+const AVI: ContentType = ContentType::new("video/x-msvideo", &["avi"]);
+const WAV: ContentType = ContentType::new("audio/wav", &["wav"]);
+const MP3: ContentType = ContentType::new("audio/mpeg", &["mp3"]);
+const OGG: ContentType = ContentType::new("audio/ogg", &["ogg", "oga", "opus"]);
+
+const KNOWN_TYPES: [ContentType; 28] = [
+    PDF, PNG, JPEG, GIF, TIFF, WEBP, HEIC, AVIF, ZIP, EPUB, DOCX, XLSX, PPTX, GZIP, XZ, BZIP2,
+    ZSTD, SEVEN_ZIP, TAR, ISO, MP4, M4A, QUICKTIME, MATROSKA, AVI, WAV, MP3, OGG,
+];
+
+// Look up the type a file extension usually stands for, for names inside archives.
+pub fn from_extension(extension: &str) -> Option<ContentType> {
+    KNOWN_TYPES
+        .into_iter()
+        .find(|content_type| content_type.accepts_extension(extension))
+}
+
+// Read the first bytes of a file and identify its format.
+pub fn detect_content_type<P: AsRef<Path>>(path: P) -> std::io::Result<Option<ContentType>> {
+    let mut buffer = Vec::new();
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut buffer)?;
+    Ok(detect_from_bytes(&buffer))
+}
+
+// Identify a format from the leading bytes of a file.
+pub fn detect_from_bytes(bytes: &[u8]) -> Option<ContentType> {
+    let at = |offset: usize, signature: &[u8]| {
+        bytes
+            .get(offset..offset + signature.len())
+            .is_some_and(|window| window == signature)
+    };
+
+    if at(0, b"%PDF-") {
+        Some(PDF)
+    } else if at(0, b"\x89PNG\r\n\x1a\n") {
+        Some(PNG)
+    } else if at(0, b"\xFF\xD8\xFF") {
+        Some(JPEG)
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        Some(GIF)
+    } else if at(0, b"II*\x00") || at(0, b"MM\x00*") {
+        Some(TIFF)
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        Some(WEBP)
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        Some(WAV)
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        Some(AVI)
+    } else if at(0, b"PK\x03\x04") || at(0, b"PK\x05\x06") {
+        Some(detect_zip_flavor(bytes))
+    } else if at(0, b"\x1F\x8B") {
+        Some(GZIP)
+    } else if at(0, b"\xFD7zXZ\x00") {
+        Some(XZ)
+    } else if at(0, b"BZh") {
+        Some(BZIP2)
+    } else if at(0, b"\x28\xB5\x2F\xFD") {
+        Some(ZSTD)
+    } else if at(0, b"7z\xBC\xAF\x27\x1C") {
+        Some(SEVEN_ZIP)
+    } else if at(4, b"ftyp") {
+        detect_iso_media_brand(bytes)
+    } else if at(0, b"\x1A\x45\xDF\xA3") {
+        Some(MATROSKA)
+    } else if at(0, b"OggS") {
+        Some(OGG)
+    } else if at(0, b"ID3") || at(0, b"\xFF\xFB") || at(0, b"\xFF\xF3") {
+        Some(MP3)
+    } else if at(257, b"ustar") {
+        Some(TAR)
+    } else if at(0x8001, b"CD001") {
+        Some(ISO)
+    } else {
+        None
+    }
+}
+
+// Tell plain zip files apart from EPUB and Office Open XML documents.
+fn detect_zip_flavor(bytes: &[u8]) -> ContentType {
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+
+    if bytes.get(30..58) == Some(b"mimetypeapplication/epub+zip".as_slice()) {
+        EPUB
+    } else if contains(b"word/") {
+        DOCX
+    } else if contains(b"xl/") {
+        XLSX
+    } else if contains(b"ppt/") {
+        PPTX
+    } else {
+        ZIP
+    }
+}
+
+/*
+Pick the ISO base media type from the major brand of the `ftyp` box. The same container holds raw
+photos (Canon's `crx `), 3GP and more, so unknown brands are left undetected.
+*/
+fn detect_iso_media_brand(bytes: &[u8]) -> Option<ContentType> {
+    match bytes.get(8..12)? {
+        b"qt  " => Some(QUICKTIME),
+        b"M4A " => Some(M4A),
+        b"heic" | b"heix" | b"mif1" | b"msf1" => Some(HEIC),
+        b"avif" | b"avis" => Some(AVIF),
+        b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1" | b"M4V "
+        | b"dash" => Some(MP4),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_from_bytes() {
+        assert_eq!(detect_from_bytes(b"%PDF-1.7\n"), Some(PDF));
+        assert_eq!(detect_from_bytes(b"\x89PNG\r\n\x1a\n...."), Some(PNG));
+        assert_eq!(detect_from_bytes(b"\x00\x00\x00\x18ftypisom"), Some(MP4));
+        assert_eq!(detect_from_bytes(b"\x00\x00\x00\x1cftypavif"), Some(AVIF));
+        assert_eq!(detect_from_bytes(b"\x00\x00\x00\x18ftypcrx "), None);
+        assert_eq!(detect_from_bytes(b"\x00\x00\x00\x14ftyp3gp5"), None);
+        assert_eq!(
+            detect_from_bytes(b"PK\x03\x04 [Content_Types].xml word/"),
+            Some(DOCX)
+        );
+        assert_eq!(detect_from_bytes(b"plain text"), None);
+
+        let mut iso = vec![0u8; SNIFF_LEN as usize];
+        iso[0x8001..0x8006].copy_from_slice(b"CD001");
+        assert_eq!(detect_from_bytes(&iso), Some(ISO));
+    }
+
+    #[test]
+    fn test_matches_mime() {
+        assert!(JPEG.matches_mime("image/jpeg"));
+        assert!(JPEG.matches_mime("image/*"));
+        assert!(!JPEG.matches_mime("video/*"));
+        assert!(JPEG.accepts_extension("JPEG"));
+        assert_eq!(JPEG.canonical_extension(), "jpg");
+        assert_eq!(from_extension("EPUB"), Some(EPUB));
+        assert_eq!(from_extension("txt"), None);
+    }
+}
@@ -11,15 +11,34 @@ impl Logger {
         Self::with_path("/usr/local/var/logs/frouter.db")
     }
 
+    // Open the regular database, for callers that carry on without it when it can't be opened
+    pub fn try_new() -> Result<Self, duckdb::Error> {
+        Self::try_with_path("/usr/local/var/logs/frouter.db")
+    }
+
     // New constructor for testing or other purposes where a custom path is needed
     pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
-        let conn = Connection::open(path).expect("Failed to open database");
+        Self::try_with_path(path).expect("Failed to open database")
+    }
+
+    pub fn try_with_path<P: AsRef<Path>>(path: P) -> Result<Self, duckdb::Error> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS logs (source TEXT, destination TEXT, filename TEXT, timestamp TEXT, filehash TEXT, action TEXT, origin_url TEXT)",
+            params![],
+        )?;
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS logs (source TEXT, destination TEXT, filename TEXT, timestamp TEXT, filehash TEXT)",
+            "CREATE TABLE IF NOT EXISTS unmatched (source TEXT PRIMARY KEY, extension TEXT, timestamp TEXT)",
             params![],
-        )
-            .expect("Failed to create logs table");
-        Logger { conn }
+        )?;
+        // Databases created by older versions lack the newer columns.
+        for column in ["action", "origin_url"] {
+            conn.execute(
+                &format!("ALTER TABLE logs ADD COLUMN IF NOT EXISTS {} TEXT", column),
+                params![],
+            )?;
+        }
+        Ok(Logger { conn })
     }
 
     pub fn start_transaction(&self) -> Result<(), duckdb::Error> {
@@ -33,6 +52,7 @@ impl Logger {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn insert_log_without_commit(
         &self,
         source: &str,
@@ -40,10 +60,26 @@ impl Logger {
         filename: &str,
         timestamp: &str,
         filehash: &str,
+        action: &str,
+        origin_url: Option<&str>,
+    ) -> Result<(), duckdb::Error> {
+        self.conn.execute(
+            "INSERT INTO logs (source, destination, filename, timestamp, filehash, action, origin_url) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params!(source, dest, filename, timestamp, filehash, action, origin_url),
+        )?;
+        Ok(())
+    }
+
+    // Files are checked again on every rescan, so only the first sighting of a path is kept.
+    pub fn insert_unmatched(
+        &self,
+        source: &str,
+        extension: &str,
+        timestamp: &str,
     ) -> Result<(), duckdb::Error> {
         self.conn.execute(
-            "INSERT INTO logs VALUES (?, ?, ?, ?, ?)",
-            params!(source, dest, filename, timestamp, filehash),
+            "INSERT OR IGNORE INTO unmatched VALUES (?, ?, ?)",
+            params!(source, extension, timestamp),
         )?;
         Ok(())
     }
@@ -71,10 +107,15 @@ mod db_tests {
             "filename.ext",
             "2023-01-01 12:00:00",
             "hash_value",
+            "move",
+            Some("https://example.com/filename.ext"),
         );
 
         assert!(result.is_ok());
 
+        let result = logger.insert_unmatched("source/data.xyz", "xyz", "2023-01-01 12:00:00");
+        assert!(result.is_ok());
+
         // Optionally, verify the inserted log...
 
         // Don't forget to end the transaction or roll it back as needed
@@ -85,7 +126,7 @@ mod db_tests {
 mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
-    use crate::test_config::load_config;
+    use crate::config_file::{merge_directories, ConfigSource};
 
     #[test]
     fn test_load_config() {
@@ -102,13 +143,14 @@ mod tests {
             "#
         ).unwrap();
 
-        let config_path = temp_file.path();
-        match load_config(config_path) {
+        let source = ConfigSource::read(temp_file.path()).unwrap();
+        match source.parse() {
             Ok(config) => {
-                assert_eq!(config.directories.get("downloads").unwrap(), "test_downloads");
+                let directories = merge_directories([(&source, &config.directories)]).unwrap();
+                assert_eq!(directories.get("downloads").unwrap().path, "test_downloads");
                 assert_eq!(config.extensions.len(), 1);
-                assert_eq!(config.extensions[0].name, "txt");
-                assert_eq!(config.extensions[0].path, "test_downloads/TXT");
+                assert_eq!(config.extensions[0].get_ref().name.as_deref(), Some("txt"));
+                assert_eq!(config.extensions[0].get_ref().path.as_ref().unwrap().get_ref(), "test_downloads/TXT");
             },
             Err(e) => panic!("Failed to load config: {:?}", e),
         }
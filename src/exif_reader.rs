@@ -0,0 +1,80 @@
+use chrono::NaiveDateTime;
+use exif::{Field, In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+// Photo metadata used for rule matching and destination templates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExifData {
+    pub captured: Option<NaiveDateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+}
+
+// Read EXIF metadata from a JPEG, HEIC, TIFF, PNG or WebP file. Returns None for other files.
+pub fn read_exif<P: AsRef<Path>>(path: P) -> Option<ExifData> {
+    let file = File::open(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    let captured = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .into_iter()
+        .filter_map(|tag| exif.get_field(tag, In::PRIMARY))
+        .filter_map(ascii_value)
+        .find_map(|value| parse_exif_datetime(&value));
+
+    Some(ExifData {
+        captured,
+        make: exif.get_field(Tag::Make, In::PRIMARY).and_then(ascii_value),
+        model: exif
+            .get_field(Tag::Model, In::PRIMARY)
+            .and_then(ascii_value),
+    })
+}
+
+// EXIF dates look like `2024:04:01 12:30:00`.
+pub fn parse_exif_datetime(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y:%m:%d %H:%M:%S").ok()
+}
+
+fn ascii_value(field: &Field) -> Option<String> {
+    match &field.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|bytes| {
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string()
+            })
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Timelike};
+
+    #[test]
+    fn test_parse_exif_datetime() {
+        let captured = parse_exif_datetime("2024:04:01 12:30:05").unwrap();
+        assert_eq!(
+            (captured.year(), captured.month(), captured.day()),
+            (2024, 4, 1)
+        );
+        assert_eq!(captured.second(), 5);
+        assert!(parse_exif_datetime("    :  :     :  :  ").is_none());
+    }
+
+    #[test]
+    fn test_read_exif_without_metadata() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.jpg");
+        std::fs::write(&path, "not really a photo").unwrap();
+        assert!(read_exif(&path).is_none());
+    }
+}
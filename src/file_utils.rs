@@ -1,13 +1,36 @@
 use crate::hash_compute::compute_sha256;
+use chrono::{DateTime, Local};
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use toml::Value;
+use std::time::{Duration, SystemTime};
+use toml::Spanned;
 
 mod error;
 
-use crate::logging::log_file_event;
-use crate::Configuration;
+use crate::archive::{list_archive, ArchiveLimits};
+use crate::condition::Condition;
+use crate::config_file::{merge_directories, ConfigDuration, ConfigSize, ConfigSource};
+use crate::content_type::detect_content_type;
+use crate::db_utils::Logger;
+use crate::error::FileRouterError;
+use crate::exif_reader::read_exif;
+use crate::hooks::{run_hook, HookContext, DEFAULT_HOOK_TIMEOUT};
+use crate::ignore::{load_ignore_file, IgnoreRules, IGNORE_FILE_NAME};
+use crate::logging::{log_error_to_file, log_file_event, log_unmatched_file};
+use crate::origin::read_origin;
+use crate::path_expand::expand_path;
+use crate::path_template::{
+    is_template, names_file, render_template, validate_file_name_template, validate_template,
+    TemplateContext,
+};
+use crate::rename::{normalize_unicode, slugify, strip_copy_suffix};
+use crate::stability::is_settled;
+use crate::text_content::{extract_text, DEFAULT_SCAN_LIMIT};
+use crate::trash::move_to_trash;
+use crate::{Configuration, MatchMode, RuleAction, TimestampKind};
 
 pub fn ensure_config_exists(home_config_path: &Path) {
     if !home_config_path.exists() {
@@ -59,10 +82,209 @@ enabled = true
     }
 }
 
-fn get_unique_target(original: &Path, target_dir: &Path) -> Result<PathBuf, std::io::Error> {
-    let mut target = target_dir.join(original.file_name().unwrap());
-    let original_extension = original.extension().unwrap_or_default();
-    let original_stem = original.file_stem().unwrap_or_default();
+/*
+How long an unmatched file stays in a watched directory before it's moved to the `_unsorted`
+destination, so there is time to open it or add a rule for it.
+*/
+const DEFAULT_UNSORTED_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/*
+How long a changed file's size and mtime have to stay the same before it is routed.
+*/
+const DEFAULT_QUIET_PERIOD: Duration = Duration::from_secs(5);
+
+/*
+In-progress downloads and lock files that are never routed, unless `temporary_files` in the
+config replaces the list. Uses the same pattern syntax as `exclude`.
+*/
+const TEMPORARY_FILE_PATTERNS: [&str; 9] = [
+    "*.crdownload",
+    "*.part",
+    "*.partial",
+    "*.download",
+    "*.opdownload",
+    "*.tmp",
+    "*.temp",
+    "~$*",
+    ".~lock.*#",
+];
+
+/*
+Compound suffixes that are kept together when a file is renamed on conflict.
+*/
+const COMPOUND_EXTENSIONS: [&str; 4] = ["tar.gz", "tar.bz2", "tar.xz", "tar.zst"];
+
+// Split a file name into its stem and suffix, keeping the longest known or compound suffix whole.
+fn split_file_name(file_name: &str, known_suffix: Option<&str>) -> (String, Option<String>) {
+    let longest_suffix = known_suffix
+        .into_iter()
+        .chain(COMPOUND_EXTENSIONS)
+        .map(|suffix| format!(".{}", suffix))
+        .filter(|dotted| dotted.len() > 1 && ends_with_ignore_case(file_name, dotted))
+        .max_by_key(|dotted| dotted.len());
+
+    if let Some(dotted) = longest_suffix {
+        let split_at = file_name.len() - dotted.len();
+        return (
+            file_name[..split_at].to_string(),
+            Some(file_name[split_at + 1..].to_string()),
+        );
+    }
+
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            (stem.to_string(), Some(extension.to_string()))
+        }
+        _ => (file_name.to_string(), None),
+    }
+}
+
+// Check for a non-empty stem followed by `suffix`, ignoring ASCII case.
+fn ends_with_ignore_case(file_name: &str, suffix: &str) -> bool {
+    file_name.len() > suffix.len()
+        && file_name
+            .get(file_name.len() - suffix.len()..)
+            .is_some_and(|tail| tail.eq_ignore_ascii_case(suffix))
+}
+
+// Build the destination file name, fixing up a wrong or missing extension when the rule asks for it.
+pub fn target_file_name(path: &Path, file_ext: &crate::FileExtension) -> String {
+    let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+    if !file_ext.fix_extension {
+        return file_name;
+    }
+
+    let content_type = match detect_content_type(path) {
+        Ok(Some(content_type)) => content_type,
+        _ => return file_name,
+    };
+
+    match path.extension() {
+        Some(extension) if content_type.accepts_extension(&extension.to_string_lossy()) => {
+            file_name
+        }
+        Some(_) => format!(
+            "{}.{}",
+            path.file_stem().unwrap_or_default().to_string_lossy(),
+            content_type.canonical_extension()
+        ),
+        None => format!("{}.{}", file_name, content_type.canonical_extension()),
+    }
+}
+
+// Work out the destination directory and file name for a file, rendering path and rename templates.
+pub fn resolve_destination(
+    path: &Path,
+    file_ext: &crate::FileExtension,
+    source: Option<&str>,
+    sha256_hash: &str,
+) -> (PathBuf, String) {
+    let mut file_name = target_file_name(path, file_ext);
+    if file_ext.strip_copy_suffix {
+        let (stem, extension) = split_file_name(&file_name, file_ext.name.as_deref());
+        file_name = join_file_name(strip_copy_suffix(&stem), extension.as_deref());
+    }
+
+    let mut target_dir = PathBuf::from(&file_ext.path);
+    if is_template(&file_ext.path) || file_ext.rename.is_some() {
+        let context = template_context(path, &file_name, file_ext, source, sha256_hash);
+
+        if let Some(rename) = &file_ext.rename {
+            let rendered = render_template(rename, &context);
+            let rendered = rendered.trim_end_matches('.');
+            if !rendered.is_empty() {
+                file_name = rendered.to_string();
+            }
+        }
+
+        if is_template(&file_ext.path) {
+            let rendered = PathBuf::from(render_template(&file_ext.path, &context));
+            if names_file(&file_ext.path) {
+                // The template names the file too, so split it off the rendered path.
+                if let Some(name) = rendered
+                    .file_name()
+                    .map(|name| name.to_string_lossy().trim_end_matches('.').to_string())
+                    .filter(|name| !name.is_empty())
+                {
+                    file_name = name;
+                }
+                target_dir = rendered.parent().map(Path::to_path_buf).unwrap_or_default();
+            } else {
+                target_dir = rendered;
+            }
+        }
+    }
+
+    (target_dir, normalize_file_name(&file_name, file_ext))
+}
+
+// Collect the values path and rename templates are rendered with.
+fn template_context(
+    path: &Path,
+    file_name: &str,
+    file_ext: &crate::FileExtension,
+    source: Option<&str>,
+    sha256_hash: &str,
+) -> TemplateContext {
+    let metadata = fs::metadata(path).ok();
+    let (stem, ext) = split_file_name(file_name, file_ext.name.as_deref());
+    let uses_exif = file_ext.path.contains("{exif.")
+        || file_ext
+            .rename
+            .as_ref()
+            .is_some_and(|rename| rename.contains("{exif."));
+
+    TemplateContext {
+        date: metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(Local::now),
+        stem,
+        ext,
+        source: source.map(|source| source.to_string()),
+        size: metadata.map_or(0, |metadata| metadata.len()),
+        sha256_hash: sha256_hash.to_string(),
+        exif: if uses_exif { read_exif(path) } else { None },
+    }
+}
+
+// Apply the rule's Unicode, slug and extension case options to a file name.
+fn normalize_file_name(file_name: &str, file_ext: &crate::FileExtension) -> String {
+    let file_name = if file_ext.normalize_unicode {
+        normalize_unicode(file_name)
+    } else {
+        file_name.to_string()
+    };
+    if !file_ext.slugify && !file_ext.lowercase_extension {
+        return file_name;
+    }
+
+    let (mut stem, mut extension) = split_file_name(&file_name, file_ext.name.as_deref());
+    if file_ext.slugify {
+        stem = slugify(&stem);
+    }
+    if file_ext.lowercase_extension {
+        extension = extension.map(|extension| extension.to_lowercase());
+    }
+    join_file_name(&stem, extension.as_deref())
+}
+
+fn join_file_name(stem: &str, extension: Option<&str>) -> String {
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_string(),
+    }
+}
+
+pub fn get_unique_target(
+    original: &Path,
+    file_name: &str,
+    target_dir: &Path,
+    known_suffix: Option<&str>,
+) -> Result<PathBuf, std::io::Error> {
+    let mut target = target_dir.join(file_name);
+    let (original_stem, original_extension) = split_file_name(file_name, known_suffix);
 
     if target.exists() {
         let original_hash = compute_sha256(original)?;
@@ -74,11 +296,9 @@ fn get_unique_target(original: &Path, target_dir: &Path) -> Result<PathBuf, std:
 
         let mut counter = 1;
         loop {
-            target = target_dir.join(format!(
-                "{}_{}.{}",
-                original_stem.to_string_lossy(),
-                counter,
-                original_extension.to_string_lossy()
+            target = target_dir.join(join_file_name(
+                &format!("{}_{}", original_stem, counter),
+                original_extension.as_deref(),
             ));
             if !target.exists() || compute_sha256(&target)? == original_hash {
                 break;
@@ -90,13 +310,38 @@ fn get_unique_target(original: &Path, target_dir: &Path) -> Result<PathBuf, std:
     Ok(target)
 }
 
-pub fn process_existing_files_in_dir(directory: &str, config: &Configuration) {
+/*
+Which files a scan of a watched directory routes. `All` applies every rule, for the files found at
+startup and on a config change. `Aged` is the periodic sweep: it only applies `older_than` rules,
+the catch-all rule included, that started to match between the previous sweep and this one, so
+files that are still young or were handled already aren't looked at.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scan {
+    All,
+    Aged {
+        since: SystemTime,
+        until: SystemTime,
+    },
+}
+
+/*
+Route the files in a watched directory. Files that are still being written are left for the
+stability tracker and returned.
+*/
+pub fn process_existing_files_in_dir(
+    directory: &str,
+    config: &Configuration,
+    scan: Scan,
+    logger: Option<&Logger>,
+) -> Vec<PathBuf> {
+    let mut in_progress = Vec::new();
     // Attempt to read directory entries
     let entries = match fs::read_dir(directory) {
         Ok(entries) => entries,
         Err(e) => {
             println!("Failed to read directory {}: {}", directory, e);
-            return;
+            return in_progress;
         }
     };
 
@@ -105,37 +350,71 @@ pub fn process_existing_files_in_dir(directory: &str, config: &Configuration) {
             Ok(entry) => {
                 let path = entry.path();
                 if path.is_file() {
-                    if let Some(extension) = get_extension_from_config(&path, &config.extensions) {
-                        let target_dir = Path::new(&extension.path);
-
-                        // Use the `get_unique_target` function
-                        let unique_target_path = get_unique_target(&path, target_dir)
-                            .expect("Failed to get a unique target path");
-
-                        // Compute the hash before moving the file
-                        let sha256_hash = match compute_sha256(&path) {
-                            Ok(hash) => hash,
-                            Err(e) => {
-                                println!(
-                                    "Failed to compute SHA256 hash for {}: {}",
-                                    path.display(),
-                                    e
-                                );
-                                continue; // skip to next iteration
+                    let (extensions, unmatched) = match scan {
+                        Scan::All => get_rules_with_fallback(&path, config),
+                        Scan::Aged { since, until } => {
+                            let aged = |rule: &&crate::FileExtension| {
+                                became_old_between(&path, rule, since, until)
+                            };
+                            // Files no rule aged for in this window only cost a stat per rule.
+                            if !config
+                                .extensions
+                                .iter()
+                                .chain(config.fallback_rules.values())
+                                .any(|rule| aged(&rule))
+                            {
+                                continue;
                             }
-                        };
+                            let (extensions, unmatched) = get_rules_with_fallback(&path, config);
+                            (extensions.into_iter().filter(aged).collect(), unmatched)
+                        }
+                    };
+                    if unmatched {
+                        log_unmatched_file(logger, &path);
+                    }
+                    if extensions.is_empty() {
+                        continue;
+                    }
+                    if !is_settled(&path, quiet_period_for(&path, config)) {
+                        in_progress.push(path);
+                        continue;
+                    }
 
-                        if let Err(e) = copy_then_delete(&path, &unique_target_path) {
+                    // Compute the hash before moving the file
+                    let sha256_hash = match compute_sha256(&path) {
+                        Ok(hash) => hash,
+                        Err(e) => {
                             println!(
-                                "Failed to move pre-existing file from {} to {}. Error: {}",
+                                "Failed to compute SHA256 hash for {}: {}",
                                 path.display(),
-                                unique_target_path.display(),
                                 e
                             );
-                        } else {
-                            log_file_event(&path, &unique_target_path, &sha256_hash);
-                            // Log the file event using the unique target path
+                            continue; // skip to next iteration
                         }
+                    };
+
+                    let source = source_directory_key(&path, config);
+                    let planned = plan_actions(&path, &extensions, source, &sha256_hash);
+                    let origin = read_origin(&path).and_then(|origin| origin.url);
+                    let (done, error) = match route_file(&path, planned, &sha256_hash) {
+                        Ok(done) => (done, None),
+                        Err(e) => (e.done, Some(e.error)),
+                    };
+                    for done in &done {
+                        log_file_event(
+                            &path,
+                            done.target.as_deref(),
+                            &sha256_hash,
+                            done.action,
+                            origin.as_deref(),
+                        );
+                    }
+                    if let Some(e) = error {
+                        println!(
+                            "Failed to route pre-existing file {}. Error: {}",
+                            path.display(),
+                            e
+                        );
                     }
                 }
             }
@@ -144,6 +423,225 @@ pub fn process_existing_files_in_dir(directory: &str, config: &Configuration) {
             }
         }
     }
+    in_progress
+}
+
+// One thing to do with a file for a matching rule, with the destination for actions that have one.
+#[derive(Debug, Clone)]
+pub struct PlannedAction<'a> {
+    pub rule: &'a crate::FileExtension,
+    pub action: RuleAction,
+    pub target: Option<PathBuf>,
+}
+
+/*
+Work out what to do with a file for each matching rule. Copies and hard links that are already in
+place are left out, so rescans don't repeat them.
+*/
+pub fn plan_actions<'a>(
+    path: &Path,
+    extensions: &[&'a crate::FileExtension],
+    source: Option<&str>,
+    sha256_hash: &str,
+) -> Vec<PlannedAction<'a>> {
+    let mut planned: Vec<PlannedAction> = Vec::new();
+    for extension in extensions {
+        if !extension.action.has_destination() {
+            if extension.action != RuleAction::Ignore {
+                planned.push(PlannedAction {
+                    rule: extension,
+                    action: extension.action,
+                    target: None,
+                });
+            }
+            continue;
+        }
+
+        // The directory is created when the action is carried out, after the `run_before` hook.
+        let (target_dir, file_name) = resolve_destination(path, extension, source, sha256_hash);
+        let target =
+            match get_unique_target(path, &file_name, &target_dir, extension.name.as_deref()) {
+                Ok(target) => target,
+                Err(e) => {
+                    let _ = log_error_to_file(
+                        "File Move Error",
+                        &format!(
+                            "Failed to get a unique target in {}: {}",
+                            target_dir.display(),
+                            e
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+        // `get_unique_target` only hands out an existing path when it holds the same content.
+        let in_place =
+            matches!(extension.action, RuleAction::Copy | RuleAction::Hardlink) && target.exists();
+        if !in_place
+            && !planned
+                .iter()
+                .any(|planned| planned.target.as_ref() == Some(&target))
+        {
+            planned.push(PlannedAction {
+                rule: extension,
+                action: extension.action,
+                target: Some(target),
+            });
+        }
+    }
+    planned
+}
+
+/*
+Route a file: run the `run_before` hooks, which can veto their rule's action by exiting non-zero,
+carry out the remaining actions and then run the `run_after` hooks of the actions that were done.
+Hook failures are written to the error log.
+*/
+pub fn route_file<'a>(
+    source: &Path,
+    planned: Vec<PlannedAction<'a>>,
+    sha256_hash: &str,
+) -> Result<Vec<PlannedAction<'a>>, RouteError<'a>> {
+    let planned: Vec<PlannedAction> = planned
+        .into_iter()
+        .filter(|planned| match &planned.rule.run_before {
+            Some(command) => run_rule_hook(command, source, planned, sha256_hash).is_ok(),
+            None => true,
+        })
+        .collect();
+
+    let result = apply_actions(source, &planned);
+    let done = match &result {
+        Ok(done) => done,
+        Err(e) => &e.done,
+    };
+    for done in done {
+        if let Some(command) = &done.rule.run_after {
+            let _ = run_rule_hook(command, source, done, sha256_hash);
+        }
+    }
+    result
+}
+
+fn run_rule_hook(
+    command: &str,
+    source: &Path,
+    planned: &PlannedAction,
+    sha256_hash: &str,
+) -> Result<(), String> {
+    let context = HookContext {
+        source,
+        destination: planned.target.as_deref(),
+        rule: planned.rule.rule_name(),
+        sha256_hash,
+        action: planned.action.as_str(),
+    };
+    run_hook(command, &context, planned.rule.run_timeout).map_err(|e| {
+        let _ = log_error_to_file(
+            "Hook Error",
+            &format!(
+                "Hook of rule {} for {}: {}",
+                context.rule,
+                source.display(),
+                e
+            ),
+        );
+        e
+    })
+}
+
+// An action that failed, with the actions done before it so they can still be logged.
+#[derive(Debug)]
+pub struct RouteError<'a> {
+    pub done: Vec<PlannedAction<'a>>,
+    pub error: std::io::Error,
+}
+
+/*
+Carry out the planned actions. Copies and hard links are made first, then the file is moved to every
+`move` and `symlink` destination. Only a file that wasn't moved anywhere is deleted or trashed.
+Returns what was done, with the destination where there is one.
+*/
+pub fn apply_actions<'a>(
+    source: &Path,
+    planned: &[PlannedAction<'a>],
+) -> Result<Vec<PlannedAction<'a>>, RouteError<'a>> {
+    let mut done = Vec::new();
+    match carry_out_actions(source, planned, &mut done) {
+        Ok(()) => Ok(done),
+        Err(error) => Err(RouteError { done, error }),
+    }
+}
+
+fn carry_out_actions<'a>(
+    source: &Path,
+    planned: &[PlannedAction<'a>],
+    done: &mut Vec<PlannedAction<'a>>,
+) -> std::io::Result<()> {
+    for planned in planned {
+        match (planned.action, &planned.target) {
+            (RuleAction::Copy, Some(target)) => {
+                ensure_parent_exists(target)?;
+                fs::copy(source, target).map(|_| ())?
+            }
+            (RuleAction::Hardlink, Some(target)) => {
+                ensure_parent_exists(target)?;
+                fs::hard_link(source, target)?
+            }
+            _ => continue,
+        }
+        done.push(planned.clone());
+    }
+
+    let is_move =
+        |planned: &&PlannedAction| matches!(planned.action, RuleAction::Move | RuleAction::Symlink);
+    let moves: Vec<PathBuf> = planned
+        .iter()
+        .filter(is_move)
+        .filter_map(|planned| planned.target.clone())
+        .collect();
+    if !moves.is_empty() {
+        for target in &moves {
+            ensure_parent_exists(target)?;
+        }
+        copy_to_all_then_delete(source, &moves)?;
+        // The file is at its destinations now, even if the link back to it can't be made.
+        done.extend(planned.iter().filter(is_move).cloned());
+        let link_target = planned
+            .iter()
+            .find(|planned| planned.action == RuleAction::Symlink)
+            .and_then(|planned| planned.target.as_ref());
+        if let Some(link_target) = link_target {
+            symlink_file(link_target, source)?;
+        }
+    } else if let Some(delete) = planned
+        .iter()
+        .find(|planned| planned.action == RuleAction::Delete)
+    {
+        fs::remove_file(source)?;
+        done.push(delete.clone());
+    } else if let Some(trash) = planned
+        .iter()
+        .find(|planned| planned.action == RuleAction::Trash)
+    {
+        let trashed = move_to_trash(source)?;
+        done.push(PlannedAction {
+            target: Some(trashed),
+            ..trash.clone()
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
 }
 
 pub fn copy_then_delete<P: AsRef<Path>, Q: AsRef<Path>>(
@@ -156,6 +654,29 @@ pub fn copy_then_delete<P: AsRef<Path>, Q: AsRef<Path>>(
     Ok(())
 }
 
+// Copy a file to every target and only remove the source once all copies succeeded.
+pub fn copy_to_all_then_delete<P: AsRef<Path>>(
+    source: P,
+    targets: &[PathBuf],
+) -> std::io::Result<()> {
+    match targets.split_last() {
+        Some((last, others)) => {
+            for target in others {
+                fs::copy(&source, target)?;
+            }
+            copy_then_delete(source, last)
+        }
+        None => Ok(()),
+    }
+}
+
+fn ensure_parent_exists(target: &Path) -> std::io::Result<()> {
+    match target.parent() {
+        Some(dir) => ensure_directory_exists(dir),
+        None => Ok(()),
+    }
+}
+
 pub fn ensure_directory_exists<P: AsRef<Path>>(dir: P) -> std::io::Result<()> {
     if !dir.as_ref().exists() {
         fs::create_dir_all(&dir)
@@ -164,78 +685,745 @@ pub fn ensure_directory_exists<P: AsRef<Path>>(dir: P) -> std::io::Result<()> {
     }
 }
 
-fn expand_home(path: &str) -> Option<PathBuf> {
-    if let Some(without_tilde) = path.strip_prefix('~') {
-        dirs::home_dir().map(|home| home.join(without_tilde.trim_start_matches('/')))
-    } else {
-        Some(PathBuf::from(path))
-    }
-}
-
 pub fn load_config(home_config_path: &Path) -> Result<Configuration, Box<dyn std::error::Error>> {
     // Load the configuration.
-    let content = fs::read_to_string(home_config_path)?;
-    let value: Value = toml::from_str(&content)?;
+    let source = ConfigSource::read(home_config_path)?;
+    let file = source.parse()?;
+    // Included files are merged after the main config, in the order they are listed.
+    let fragments = file.read_includes(&source)?;
+    let directories = merge_directories(
+        std::iter::once((&source, &file.directories)).chain(
+            fragments
+                .iter()
+                .map(|(fragment_source, fragment)| (fragment_source, &fragment.directories)),
+        ),
+    )?;
 
-    let directories = value["directories"].as_table().unwrap().clone();
     let mut expanded_directories = HashMap::new();
-    for (k, v) in directories.iter() {
-        if k.ends_with("_enabled") {
+    let mut directory_excludes = HashMap::new();
+    let mut directory_quiet_periods = HashMap::new();
+    let mut fallback_rules = HashMap::new();
+    for (name, directory) in directories
+        .iter()
+        .filter(|(_, directory)| directory.enabled)
+    {
+        expanded_directories.insert(name.clone(), directory.path.clone());
+        if let Some(excludes) = &directory.exclude {
+            directory_excludes.insert(name.clone(), excludes.clone());
+        }
+        if let Some(quiet_period) = directory.quiet_period {
+            directory_quiet_periods.insert(name.clone(), quiet_period);
+        }
+        if let Some(unsorted) = &directory.unsorted {
+            fallback_rules.insert(
+                name.clone(),
+                crate::FileExtension {
+                    rule: Some("unsorted".to_string()),
+                    sources: vec![name.clone()],
+                    path: unsorted.clone(),
+                    older_than: Some(directory.unsorted_after.unwrap_or(DEFAULT_UNSORTED_AFTER)),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let mut archive_limits = ArchiveLimits::default();
+    if let Some(max_entries) = file.archive_max_entries {
+        archive_limits.max_entries = max_entries;
+    }
+    if let Some(ConfigSize(max_size)) = file.archive_max_size {
+        archive_limits.max_size = max_size;
+    }
+
+    let mut extensions: Vec<crate::FileExtension> = Vec::new();
+    let rules = std::iter::once((&source, &file.extensions))
+        .chain(
+            fragments
+                .iter()
+                .map(|(fragment_source, fragment)| (fragment_source, &fragment.extensions)),
+        )
+        .flat_map(|(source, extensions)| extensions.iter().map(move |rule| (source, rule)));
+    for (source, spanned_extension) in rules {
+        let extension = spanned_extension.get_ref();
+        if !extension.enabled {
             continue;
         }
 
-        let key_enabled = format!("{}_enabled", k);
-        if directories
-            .get(&key_enabled)
-            .map_or(false, |val| val.as_bool().unwrap_or(false))
-        {
-            if let Some(expanded_path) = expand_home(v.as_str().unwrap()) {
-                expanded_directories.insert(k.clone(), expanded_path.to_string_lossy().to_string());
+        // Only actions that put the file somewhere need a destination.
+        let path = match &extension.path {
+            Some(path) => {
+                let expanded_path = expand_path(path.get_ref())
+                    .map_err(|e| source.error(path.span(), "path", e))?;
+                validate_template(&expanded_path)
+                    .map_err(|e| source.error(path.span(), "path", e))?;
+                expanded_path
             }
+            None if !extension.action.has_destination() => String::new(),
+            None => {
+                return Err(Box::new(source.error(
+                    spanned_extension.span(),
+                    "extensions",
+                    format!(
+                        "Rule with action {} needs a path",
+                        extension.action.as_str()
+                    ),
+                )))
+            }
+        };
+
+        if let Some(rename) = &extension.rename {
+            validate_file_name_template(rename.get_ref())
+                .map_err(|e| source.error(rename.span(), "rename", e))?;
         }
-    }
 
-    let mut extensions: Vec<crate::FileExtension> = Vec::new();
-    for extension in value["extensions"].as_array().unwrap() {
-        if extension
-            .get("enabled")
-            .map_or(false, |val| val.as_bool().unwrap_or(false))
+        // Optional matchers on the full file name.
+        let glob = compile_glob(source, "glob", &extension.glob)?;
+        let regex = compile_regex(source, "regex", &extension.regex)?;
+        let condition = match &extension.when {
+            Some(when) => Some(Condition::parse(when.get_ref()).map_err(|e| {
+                source.error(when.span(), "when", format!("Invalid condition at {}", e))
+            })?),
+            None => None,
+        };
+        let camera = compile_glob(source, "camera", &extension.camera)?;
+        let archive_contains =
+            compile_glob(source, "archive_contains", &extension.archive_contains)?;
+        let origin_url = compile_glob(source, "origin_url", &extension.origin_url)?;
+        let text_regex = compile_regex(source, "text_regex", &extension.text_regex)?;
+
+        if extension.name.is_none()
+            && glob.is_none()
+            && regex.is_none()
+            && condition.is_none()
+            && !extension.no_extension
+            && extension.mime.is_none()
+            && camera.is_none()
+            && extension.archive_type.is_none()
+            && archive_contains.is_none()
+            && extension.origin_host.is_none()
+            && origin_url.is_none()
+            && extension.text_contains.is_empty()
+            && text_regex.is_none()
         {
-            let name = extension["name"].as_str().unwrap().to_string();
-            let path = if let Some(expanded_path) = expand_home(extension["path"].as_str().unwrap())
-            {
-                expanded_path.to_string_lossy().to_string()
-            } else {
-                extension["path"].as_str().unwrap().to_string()
-            };
-            extensions.push(crate::FileExtension { name, path });
+            return Err(Box::new(source.error(
+                spanned_extension.span(),
+                "extensions",
+                format!(
+                    "Rule for {} needs at least one of name, when, glob, regex, no_extension, mime, camera, archive_type, archive_contains, origin_host, origin_url, text_contains or text_regex",
+                    path
+                ),
+            )));
         }
+
+        let mut sources = Vec::new();
+        for key in &extension.sources {
+            if !directories.contains_key(key.get_ref()) {
+                return Err(Box::new(source.error(
+                    key.span(),
+                    "sources",
+                    format!("Unknown source directory {}", key.get_ref()),
+                )));
+            }
+            sources.push(key.get_ref().clone());
+        }
+
+        extensions.push(crate::FileExtension {
+            rule: extension.rule.clone(),
+            priority: extension.priority,
+            sources,
+            name: extension.name.clone(),
+            path,
+            glob,
+            regex,
+            condition,
+            no_extension: extension.no_extension,
+            mime: extension.mime.clone(),
+            fix_extension: extension.fix_extension,
+            min_size: extension.min_size.map(|ConfigSize(size)| size),
+            max_size: extension.max_size.map(|ConfigSize(size)| size),
+            older_than: extension.older_than.map(|ConfigDuration(age)| age),
+            newer_than: extension.newer_than.map(|ConfigDuration(age)| age),
+            timestamp: extension.timestamp,
+            camera,
+            archive_type: extension.archive_type.clone(),
+            archive_contains,
+            archive_limits,
+            text_contains: extension.text_contains.clone(),
+            text_regex,
+            text_scan_limit: extension
+                .text_scan_limit
+                .map_or(DEFAULT_SCAN_LIMIT, |ConfigSize(limit)| limit),
+            origin_host: extension.origin_host.clone(),
+            origin_url,
+            action: extension.action,
+            run_before: extension.run_before.clone(),
+            run_after: extension.run_after.clone(),
+            run_timeout: extension
+                .run_timeout
+                .map_or(DEFAULT_HOOK_TIMEOUT, |ConfigDuration(timeout)| timeout),
+            rename: extension
+                .rename
+                .as_ref()
+                .map(|rename| rename.get_ref().clone()),
+            strip_copy_suffix: extension.strip_copy_suffix,
+            slugify: extension.slugify,
+            lowercase_extension: extension.lowercase_extension,
+            normalize_unicode: extension.normalize_unicode,
+        });
     }
 
+    // Higher priority first; the sort is stable so equal priorities keep their file order.
+    extensions.sort_by_key(|extension| std::cmp::Reverse(extension.priority));
+
+    let excludes = match &file.exclude {
+        Some(patterns) => parse_excludes(&source, "exclude", patterns)?,
+        None => IgnoreRules::default(),
+    };
+    let temporary_files = match &file.temporary_files {
+        Some(patterns) => parse_excludes(&source, "temporary_files", patterns)?,
+        None => {
+            IgnoreRules::parse(TEMPORARY_FILE_PATTERNS).map_err(FileRouterError::ConfigError)?
+        }
+    };
+
+    let quiet_period = file
+        .quiet_period
+        .map_or(DEFAULT_QUIET_PERIOD, |ConfigDuration(quiet_period)| {
+            quiet_period
+        });
+
     Ok(Configuration {
         directories: expanded_directories,
         extensions,
+        match_mode: file.match_mode,
+        excludes,
+        directory_excludes,
+        temporary_files,
+        quiet_period,
+        directory_quiet_periods,
+        fallback_rules,
+        includes: file.include_patterns(&source)?,
     })
 }
 
+// Parse an array of exclude patterns from the config.
+fn parse_excludes(
+    source: &ConfigSource,
+    key: &str,
+    patterns: &Spanned<Vec<String>>,
+) -> Result<IgnoreRules, FileRouterError> {
+    IgnoreRules::parse(patterns.get_ref().iter().map(|pattern| pattern.as_str()))
+        .map_err(|e| source.error(patterns.span(), key, e))
+}
+
+fn compile_glob(
+    source: &ConfigSource,
+    key: &str,
+    pattern: &Option<Spanned<String>>,
+) -> Result<Option<Pattern>, FileRouterError> {
+    pattern
+        .as_ref()
+        .map(|pattern| {
+            Pattern::new(pattern.get_ref()).map_err(|e| {
+                source.error(
+                    pattern.span(),
+                    key,
+                    format!("Invalid pattern '{}': {}", pattern.get_ref(), e),
+                )
+            })
+        })
+        .transpose()
+}
+
+fn compile_regex(
+    source: &ConfigSource,
+    key: &str,
+    pattern: &Option<Spanned<String>>,
+) -> Result<Option<Regex>, FileRouterError> {
+    pattern
+        .as_ref()
+        .map(|pattern| {
+            Regex::new(pattern.get_ref()).map_err(|e| {
+                source.error(
+                    pattern.span(),
+                    key,
+                    format!("Invalid regex '{}': {}", pattern.get_ref(), e),
+                )
+            })
+        })
+        .transpose()
+}
+
+// Parse a size such as `500MB`, `1.5GiB` or `1024` into bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split_at = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split_at);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "T" | "TB" => 1_000_000_000_000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        "TIB" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+// Parse a duration such as `30d`, `12h`, `1w2d` or `90` (seconds).
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if duration.is_empty() {
+        return None;
+    }
+    if let Ok(seconds) = duration.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        // Values too large for a duration are rejected rather than wrapped around.
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(multiplier)?)?;
+        number.clear();
+    }
+
+    // A trailing number without a unit is ambiguous.
+    if number.is_empty() {
+        Some(Duration::from_secs(total))
+    } else {
+        None
+    }
+}
+
+// Check if a rule's `older_than` condition started to hold for a file after `since`, up to `until`.
+fn became_old_between(
+    path: &Path,
+    file_ext: &crate::FileExtension,
+    since: SystemTime,
+    until: SystemTime,
+) -> bool {
+    let older_than = match file_ext.older_than {
+        Some(older_than) => older_than,
+        None => return false,
+    };
+    fs::metadata(path)
+        .ok()
+        .and_then(|metadata| file_timestamp(&metadata, file_ext.timestamp))
+        .and_then(|time| time.checked_add(older_than))
+        .is_some_and(|old_at| since < old_at && old_at <= until)
+}
+
+// Read the timestamp a rule's age conditions are checked against.
+fn file_timestamp(metadata: &fs::Metadata, kind: TimestampKind) -> Option<SystemTime> {
+    match kind {
+        TimestampKind::Modified => metadata.modified().ok(),
+        TimestampKind::Accessed => metadata.accessed().ok(),
+        #[cfg(unix)]
+        TimestampKind::Changed => {
+            use std::os::unix::fs::MetadataExt;
+            let seconds = u64::try_from(metadata.ctime()).ok()?;
+            Some(SystemTime::UNIX_EPOCH + Duration::new(seconds, metadata.ctime_nsec() as u32))
+        }
+        #[cfg(not(unix))]
+        TimestampKind::Changed => metadata.created().ok(),
+    }
+}
+
 pub fn get_extension_from_config<'a>(
     path: &Path,
+    source: Option<&str>,
     extensions: &'a [crate::FileExtension],
 ) -> Option<&'a crate::FileExtension> {
-    // Convert the file extension to lowercase for case-insensitive comparison
-    path.extension().and_then(|os_str| {
-        os_str.to_str().and_then(|ext| {
-            let ext_lower = ext.to_lowercase(); // Convert to lowercase
-            extensions.iter().find(|file_ext| file_ext.name.to_lowercase() == ext_lower)
-        })
+    // Rules are sorted by priority at load time, so the first match is the one to use.
+    extensions
+        .iter()
+        .find(|file_ext| in_scope(file_ext, source) && extension_matches(path, file_ext))
+}
+
+/*
+Get the rules a file should be routed by. When no configured rule matches, the catch-all rule of
+its watched directory applies once the grace period is over, and the returned flag tells the caller
+to record the file as unmatched. Directories moved into a watched directory are never unmatched.
+*/
+pub fn get_rules_with_fallback<'a>(
+    path: &Path,
+    config: &'a Configuration,
+) -> (Vec<&'a crate::FileExtension>, bool) {
+    let extensions = get_extensions_from_config(path, config);
+    if !extensions.is_empty() || !path.is_file() || is_skipped(path, config) {
+        return (extensions, false);
+    }
+
+    (get_fallback_rule(path, config).into_iter().collect(), true)
+}
+
+// Get the catch-all rule for a file if its grace period is over. Hidden files and directories are
+// left alone.
+pub fn get_fallback_rule<'a>(
+    path: &Path,
+    config: &'a Configuration,
+) -> Option<&'a crate::FileExtension> {
+    if !path.is_file() {
+        return None;
+    }
+    let hidden = path
+        .file_name()
+        .and_then(|os_str| os_str.to_str())
+        .is_none_or(|file_name| file_name.starts_with('.'));
+    if hidden {
+        return None;
+    }
+
+    source_directory_key(path, config)
+        .and_then(|key| config.fallback_rules.get(key))
+        .filter(|fallback| extension_matches(path, fallback))
+}
+
+// Get every rule a file should be routed by, honoring the configured match mode.
+pub fn get_extensions_from_config<'a>(
+    path: &Path,
+    config: &'a Configuration,
+) -> Vec<&'a crate::FileExtension> {
+    if is_skipped(path, config) {
+        return Vec::new();
+    }
+
+    let source = source_directory_key(path, config);
+    match config.match_mode {
+        MatchMode::First => get_extension_from_config(path, source, &config.extensions)
+            .into_iter()
+            .collect(),
+        MatchMode::All => config
+            .extensions
+            .iter()
+            .filter(|file_ext| in_scope(file_ext, source) && extension_matches(path, file_ext))
+            .collect(),
+    }
+}
+
+// Find the key of the watched directory a file lives in, preferring the deepest match.
+pub fn source_directory_key<'a>(path: &Path, config: &'a Configuration) -> Option<&'a str> {
+    config
+        .directories
+        .iter()
+        .filter(|(_, dir)| path.starts_with(dir))
+        .max_by_key(|(_, dir)| dir.len())
+        .map(|(key, _)| key.as_str())
+}
+
+// Check if a file is one of the config files pulled in by `include`.
+pub fn is_included_config(path: &Path, config: &Configuration) -> bool {
+    config
+        .includes
+        .iter()
+        .any(|pattern| pattern.matches_path(path))
+}
+
+/*
+Check if a file is an editor's backup, swap or lock file for the config or a file it includes, such
+as `config.toml~`, `.config.toml.swp` or `.#config.toml`. Everything else next to the config files
+is an ordinary file, which matters when they live in a watched directory.
+*/
+pub fn is_config_backup(path: &Path, config_path: &Path, config: &Configuration) -> bool {
+    let file_name = match path.file_name().and_then(|os_str| os_str.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    let swap_file = |suffix| {
+        file_name
+            .strip_prefix('.')
+            .and_then(|name| name.strip_suffix(suffix))
+    };
+    let originals = [
+        file_name.strip_suffix('~'),
+        file_name.strip_suffix(".bak"),
+        file_name.strip_prefix(".#"),
+        file_name
+            .strip_prefix('#')
+            .and_then(|name| name.strip_suffix('#')),
+        swap_file(".swp"),
+        swap_file(".swx"),
+    ];
+    originals.into_iter().flatten().any(|original| {
+        let original = path.with_file_name(original);
+        original == config_path || is_included_config(&original, config)
+    })
+}
+
+// Check if a file is never routed, no matter which rules match it.
+fn is_skipped(path: &Path, config: &Configuration) -> bool {
+    // Links left behind by `symlink` rules point at files that were routed already.
+    is_temporary(path, config)
+        || has_temporary_sibling(path, config)
+        || is_excluded(path, config)
+        || path.is_symlink()
+}
+
+// Get the quiet period for the watched directory a file lives in.
+pub fn quiet_period_for(path: &Path, config: &Configuration) -> Duration {
+    source_directory_key(path, config)
+        .and_then(|key| config.directory_quiet_periods.get(key))
+        .copied()
+        .unwrap_or(config.quiet_period)
+}
+
+// Check if a file is a partly written download or an editor lock file.
+pub fn is_temporary(path: &Path, config: &Configuration) -> bool {
+    path.file_name()
+        .and_then(|os_str| os_str.to_str())
+        .is_some_and(|file_name| config.temporary_files.is_ignored(file_name, false))
+}
+
+/*
+Check if a file is still being downloaded through a temporary file next to it. Firefox creates an
+empty `report.pdf` while it writes `report.pdf.part`, and aria2 writes `setup.iso` in place while
+`setup.iso.aria2` exists.
+*/
+fn has_temporary_sibling(path: &Path, config: &Configuration) -> bool {
+    let file_name = match path.file_name().and_then(|os_str| os_str.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    config.temporary_files.suffixes().iter().any(|suffix| {
+        path.with_file_name(format!("{}{}", file_name, suffix))
+            .exists()
     })
 }
 
+// Check a file against the global, per-directory and `.frouterignore` exclude patterns.
+pub fn is_excluded(path: &Path, config: &Configuration) -> bool {
+    let file_name = match path.file_name().and_then(|os_str| os_str.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    if file_name == IGNORE_FILE_NAME {
+        return true;
+    }
+
+    let mut rules = config.excludes.clone();
+    if let Some(dir_rules) =
+        source_directory_key(path, config).and_then(|key| config.directory_excludes.get(key))
+    {
+        rules.extend(dir_rules);
+    }
+    // The ignore file is read on every check, so edits apply without a config reload.
+    if let Some(parent) = path.parent() {
+        match load_ignore_file(parent) {
+            Ok(dir_rules) => rules.extend(&dir_rules),
+            Err(e) => {
+                let _ = log_error_to_file("Ignore File Error", &e);
+            }
+        }
+    }
+
+    rules.is_ignored(file_name, path.is_dir())
+}
+
+// Rules without `sources` apply to every watched directory.
+fn in_scope(file_ext: &crate::FileExtension, source: Option<&str>) -> bool {
+    file_ext.sources.is_empty()
+        || source.is_some_and(|source| file_ext.sources.iter().any(|key| key == source))
+}
+
+// Check a single rule against a file. Every matcher set on the rule must match.
+fn extension_matches(path: &Path, file_ext: &crate::FileExtension) -> bool {
+    let file_name = match path.file_name().and_then(|os_str| os_str.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+
+    if let Some(name) = &file_ext.name {
+        // Compare the trailing suffix case-insensitively so `tar.gz` style names work too.
+        if !ends_with_ignore_case(file_name, &format!(".{}", name)) {
+            return false;
+        }
+    }
+
+    if file_ext.no_extension && path.extension().is_some() {
+        return false;
+    }
+
+    if let Some(glob) = &file_ext.glob {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        if !glob.matches_with(file_name, options) {
+            return false;
+        }
+    }
+
+    if let Some(regex) = &file_ext.regex {
+        if !regex.is_match(file_name) {
+            return false;
+        }
+    }
+
+    if file_ext
+        .condition
+        .as_ref()
+        .is_some_and(|condition| !condition.matches(path))
+    {
+        return false;
+    }
+
+    if file_ext.min_size.is_some() || file_ext.max_size.is_some() {
+        let size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return false,
+        };
+        // `min_size` is inclusive and `max_size` exclusive, so adjacent rules don't overlap.
+        if file_ext.min_size.is_some_and(|min_size| size < min_size)
+            || file_ext.max_size.is_some_and(|max_size| size >= max_size)
+        {
+            return false;
+        }
+    }
+
+    if file_ext.older_than.is_some() || file_ext.newer_than.is_some() {
+        let age = match fs::metadata(path)
+            .ok()
+            .and_then(|metadata| file_timestamp(&metadata, file_ext.timestamp))
+        {
+            // Timestamps in the future count as brand new.
+            Some(time) => SystemTime::now().duration_since(time).unwrap_or_default(),
+            None => return false,
+        };
+        if file_ext
+            .older_than
+            .is_some_and(|older_than| age < older_than)
+            || file_ext
+                .newer_than
+                .is_some_and(|newer_than| age >= newer_than)
+        {
+            return false;
+        }
+    }
+
+    if file_ext.origin_host.is_some() || file_ext.origin_url.is_some() {
+        let origin = match read_origin(path) {
+            Some(origin) => origin,
+            None => return false,
+        };
+        if file_ext
+            .origin_host
+            .as_ref()
+            .is_some_and(|host| !origin.matches_host(host))
+        {
+            return false;
+        }
+        if let Some(origin_url) = &file_ext.origin_url {
+            let options = MatchOptions {
+                case_sensitive: false,
+                ..MatchOptions::new()
+            };
+            if !origin
+                .urls()
+                .any(|url| origin_url.matches_with(url, options))
+            {
+                return false;
+            }
+        }
+    }
+
+    // Content sniffing and EXIF read the file, so they run after the cheaper checks.
+    if let Some(camera) = &file_ext.camera {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let model = read_exif(path).and_then(|exif| exif.model);
+        if !model.is_some_and(|model| camera.matches_with(&model, options)) {
+            return false;
+        }
+    }
+
+    if let Some(mime) = &file_ext.mime {
+        let detected = detect_content_type(path).ok().flatten();
+        if !detected.is_some_and(|content_type| content_type.matches_mime(mime)) {
+            return false;
+        }
+    }
+
+    if !file_ext.text_contains.is_empty() || file_ext.text_regex.is_some() {
+        let text = match extract_text(path, file_ext.text_scan_limit) {
+            Some(text) => text,
+            None => return false,
+        };
+        let lowercase_text = text.to_lowercase();
+        if !file_ext.text_contains.is_empty()
+            && !file_ext
+                .text_contains
+                .iter()
+                .any(|keyword| lowercase_text.contains(&keyword.to_lowercase()))
+        {
+            return false;
+        }
+        if file_ext
+            .text_regex
+            .as_ref()
+            .is_some_and(|regex| !regex.is_match(&text))
+        {
+            return false;
+        }
+    }
+
+    if file_ext.archive_type.is_some() || file_ext.archive_contains.is_some() {
+        let listing = match list_archive(path, &file_ext.archive_limits) {
+            Ok(Some(listing)) => listing,
+            Ok(None) => return false,
+            Err(e) => {
+                let _ = log_error_to_file(
+                    "Archive Inspection Error",
+                    &format!("Skipped {}: {}", path.display(), e),
+                );
+                return false;
+            }
+        };
+        if file_ext
+            .archive_type
+            .as_ref()
+            .is_some_and(|mime| !listing.dominant_type_is(mime))
+            || file_ext
+                .archive_contains
+                .as_ref()
+                .is_some_and(|pattern| !listing.contains(pattern))
+        {
+            return false;
+        }
+    }
+
+    true
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
+    use std::io::Write;
     use std::path::PathBuf;
 
     #[test]
@@ -252,4 +1440,761 @@ mod tests {
         // Teardown: Clean up the test directory after the test
         fs::remove_dir_all(test_dir).unwrap();
     }
+
+    #[test]
+    fn test_get_extension_from_config_with_glob_and_regex() {
+        let extensions = vec![
+            crate::FileExtension {
+                name: Some("pdf".to_string()),
+                path: "Invoices".to_string(),
+                glob: Some(Pattern::new("invoice_*").unwrap()),
+                ..Default::default()
+            },
+            crate::FileExtension {
+                path: "Screenshots".to_string(),
+                regex: Some(Regex::new(r"^Screenshot .*\.png$").unwrap()),
+                ..Default::default()
+            },
+            crate::FileExtension {
+                name: Some("pdf".to_string()),
+                path: "PDF".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let matched = |name: &str| {
+            get_extension_from_config(Path::new(name), None, &extensions).map(|e| e.path.as_str())
+        };
+
+        assert_eq!(matched("Invoice_2024.PDF"), Some("Invoices"));
+        assert_eq!(matched("report.pdf"), Some("PDF"));
+        assert_eq!(matched("Screenshot 2024-04-01.png"), Some("Screenshots"));
+        assert_eq!(matched("photo.png"), None);
+    }
+
+    #[test]
+    fn test_get_extension_from_config_compound_and_extensionless() {
+        let extensions = vec![
+            crate::FileExtension {
+                name: Some("tar.gz".to_string()),
+                path: "Tarballs".to_string(),
+                ..Default::default()
+            },
+            crate::FileExtension {
+                name: Some("gz".to_string()),
+                path: "Gzip".to_string(),
+                ..Default::default()
+            },
+            crate::FileExtension {
+                path: "Build".to_string(),
+                glob: Some(Pattern::new("makefile").unwrap()),
+                no_extension: true,
+                ..Default::default()
+            },
+        ];
+
+        let matched = |name: &str| {
+            get_extension_from_config(Path::new(name), None, &extensions).map(|e| e.path.as_str())
+        };
+
+        assert_eq!(matched("backup.TAR.GZ"), Some("Tarballs"));
+        assert_eq!(matched("access.log.gz"), Some("Gzip"));
+        assert_eq!(matched("Makefile"), Some("Build"));
+        assert_eq!(matched("makefile.bak"), None);
+        assert_eq!(matched(".gz"), None);
+    }
+
+    #[test]
+    fn test_get_unique_target_keeps_compound_suffix() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let target_dir = tempfile::TempDir::new().unwrap();
+
+        for name in ["backup.tar.gz", "README"] {
+            fs::write(source_dir.path().join(name), "new").unwrap();
+            fs::write(target_dir.path().join(name), "old").unwrap();
+        }
+
+        let target = get_unique_target(
+            &source_dir.path().join("backup.tar.gz"),
+            "backup.tar.gz",
+            target_dir.path(),
+            Some("gz"),
+        )
+        .unwrap();
+        assert_eq!(target, target_dir.path().join("backup_1.tar.gz"));
+
+        let target = get_unique_target(
+            &source_dir.path().join("README"),
+            "README",
+            target_dir.path(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(target, target_dir.path().join("README_1"));
+    }
+
+    #[test]
+    fn test_mime_rule_fixes_extension() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let path = source_dir.path().join("download.php");
+        fs::write(&path, "%PDF-1.4\n").unwrap();
+
+        let extensions = vec![crate::FileExtension {
+            path: "PDF".to_string(),
+            mime: Some("application/pdf".to_string()),
+            fix_extension: true,
+            ..Default::default()
+        }];
+
+        let extension = get_extension_from_config(&path, None, &extensions).unwrap();
+        assert_eq!(target_file_name(&path, extension), "download.pdf");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Some(1024));
+        assert_eq!(parse_size("500MB"), Some(500_000_000));
+        assert_eq!(parse_size("1.5 GiB"), Some(1_610_612_736));
+        assert_eq!(parse_size("4kb"), Some(4_000));
+        assert_eq!(parse_size("12 parsecs"), None);
+        assert_eq!(parse_size("MB"), None);
+    }
+
+    #[test]
+    fn test_get_extension_from_config_with_size_limits() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let small = source_dir.path().join("small.iso");
+        let large = source_dir.path().join("large.iso");
+        fs::write(&small, vec![0u8; 10]).unwrap();
+        fs::write(&large, vec![0u8; 2_000]).unwrap();
+
+        let extensions = vec![
+            crate::FileExtension {
+                name: Some("iso".to_string()),
+                path: "Bulk".to_string(),
+                min_size: Some(1_000),
+                ..Default::default()
+            },
+            crate::FileExtension {
+                name: Some("iso".to_string()),
+                path: "Local".to_string(),
+                max_size: Some(1_000),
+                ..Default::default()
+            },
+        ];
+
+        let matched = |path: &Path| {
+            get_extension_from_config(path, None, &extensions).map(|e| e.path.as_str())
+        };
+
+        assert_eq!(matched(&large), Some("Bulk"));
+        assert_eq!(matched(&small), Some("Local"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(
+            parse_duration("30d"),
+            Some(Duration::from_secs(30 * 86_400))
+        );
+        assert_eq!(
+            parse_duration("1w2d"),
+            Some(Duration::from_secs(9 * 86_400))
+        );
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5_400)));
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("9999999999999999w"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    }
+
+    #[test]
+    fn test_get_extension_from_config_with_age() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let path = source_dir.path().join("notes.txt");
+        fs::write(&path, "fresh").unwrap();
+
+        let stale_rule = vec![crate::FileExtension {
+            name: Some("txt".to_string()),
+            path: "Archive".to_string(),
+            older_than: Some(Duration::from_secs(30 * 86_400)),
+            ..Default::default()
+        }];
+        let fresh_rule = vec![crate::FileExtension {
+            name: Some("txt".to_string()),
+            path: "Inbox".to_string(),
+            newer_than: Some(Duration::from_secs(3_600)),
+            ..Default::default()
+        }];
+
+        assert!(get_extension_from_config(&path, None, &stale_rule).is_none());
+        assert!(get_extension_from_config(&path, None, &fresh_rule).is_some());
+    }
+
+    #[test]
+    fn test_load_config_priority_and_match_mode() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(
+                br#"
+match_mode = "all"
+
+[directories]
+
+[[extensions]]
+rule = "all-pdfs"
+name = "pdf"
+path = "PDF"
+enabled = true
+
+[[extensions]]
+rule = "invoices"
+name = "pdf"
+glob = "invoice_*"
+path = "Invoices"
+priority = 10
+enabled = true
+"#,
+            )
+            .unwrap();
+
+        let config = load_config(temp_file.path()).unwrap();
+        assert_eq!(config.match_mode, MatchMode::All);
+        assert_eq!(config.extensions[0].rule_name(), "invoices");
+
+        let matched: Vec<&str> = get_extensions_from_config(Path::new("invoice_1.pdf"), &config)
+            .iter()
+            .map(|e| e.rule_name())
+            .collect();
+        assert_eq!(matched, vec!["invoices", "all-pdfs"]);
+    }
+
+    #[test]
+    fn test_get_extensions_from_config_with_sources() {
+        let mut config = Configuration::default();
+        config
+            .directories
+            .insert("downloads".to_string(), "/home/user/Downloads".to_string());
+        config
+            .directories
+            .insert("scans".to_string(), "/home/user/Scans".to_string());
+        config.extensions = vec![
+            crate::FileExtension {
+                name: Some("pdf".to_string()),
+                path: "Inbox".to_string(),
+                sources: vec!["downloads".to_string()],
+                ..Default::default()
+            },
+            crate::FileExtension {
+                name: Some("pdf".to_string()),
+                path: "Archive/Scans".to_string(),
+                sources: vec!["scans".to_string()],
+                ..Default::default()
+            },
+        ];
+
+        let matched = |path: &str| {
+            get_extensions_from_config(Path::new(path), &config)
+                .first()
+                .map(|e| e.path.clone())
+        };
+
+        assert_eq!(
+            matched("/home/user/Downloads/a.pdf"),
+            Some("Inbox".to_string())
+        );
+        assert_eq!(
+            matched("/home/user/Scans/b.pdf"),
+            Some("Archive/Scans".to_string())
+        );
+        assert_eq!(matched("/tmp/c.pdf"), None);
+    }
+
+    #[test]
+    fn test_resolve_destination_with_template() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let path = source_dir.path().join("Backup.TAR.GZ");
+        fs::write(&path, "data").unwrap();
+        let year = Local::now().format("%Y").to_string();
+
+        let directory_rule = crate::FileExtension {
+            name: Some("tar.gz".to_string()),
+            path: "/Archive/{ext}/{year}".to_string(),
+            ..Default::default()
+        };
+        let (target_dir, file_name) =
+            resolve_destination(&path, &directory_rule, Some("downloads"), "abc123");
+        assert_eq!(
+            target_dir,
+            PathBuf::from(format!("/Archive/tar.gz/{}", year))
+        );
+        assert_eq!(file_name, "Backup.TAR.GZ");
+
+        let file_rule = crate::FileExtension {
+            name: Some("tar.gz".to_string()),
+            path: "/Archive/{source}/{hash:3}_{stem}.{ext}".to_string(),
+            ..Default::default()
+        };
+        let (target_dir, file_name) =
+            resolve_destination(&path, &file_rule, Some("downloads"), "abc123");
+        assert_eq!(target_dir, PathBuf::from("/Archive/downloads"));
+        assert_eq!(file_name, "abc_Backup.tar.gz");
+    }
+
+    #[test]
+    fn test_resolve_destination_with_rename() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let target_dir = tempfile::TempDir::new().unwrap();
+        let path = source_dir.path().join("Quarterly Report! (1).PDF");
+        fs::write(&path, "new").unwrap();
+        fs::write(target_dir.path().join("data-quarterly-report.pdf"), "old").unwrap();
+
+        let extension = crate::FileExtension {
+            name: Some("pdf".to_string()),
+            path: target_dir.path().to_string_lossy().to_string(),
+            rename: Some("Data {stem}.{ext}".to_string()),
+            strip_copy_suffix: true,
+            slugify: true,
+            lowercase_extension: true,
+            ..Default::default()
+        };
+
+        let (resolved_dir, file_name) = resolve_destination(&path, &extension, None, "abc123");
+        assert_eq!(file_name, "data-quarterly-report.pdf");
+
+        let target = get_unique_target(&path, &file_name, &resolved_dir, Some("pdf")).unwrap();
+        assert_eq!(
+            target,
+            target_dir.path().join("data-quarterly-report_1.pdf")
+        );
+    }
+
+    #[test]
+    fn test_load_config_excludes() {
+        let watched_dir = tempfile::TempDir::new().unwrap();
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            r#"
+exclude = [".*"]
+
+[directories]
+downloads = "{}"
+downloads_enabled = true
+downloads_exclude = ["draft_*"]
+
+[[extensions]]
+name = "pdf"
+path = "PDF"
+enabled = true
+"#,
+            watched_dir.path().display()
+        )
+        .unwrap();
+        fs::write(watched_dir.path().join(IGNORE_FILE_NAME), "private.pdf\n").unwrap();
+
+        let config = load_config(temp_file.path()).unwrap();
+        let matches = |name: &str| {
+            !get_extensions_from_config(&watched_dir.path().join(name), &config).is_empty()
+        };
+
+        assert!(matches("report.pdf"));
+        assert!(!matches(".hidden.pdf"));
+        assert!(!matches("draft_report.pdf"));
+        assert!(!matches("private.pdf"));
+    }
+
+    #[test]
+    fn test_temporary_files_are_not_routed() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(
+                br#"
+[directories]
+
+[[extensions]]
+glob = "*"
+path = "Everything"
+enabled = true
+"#,
+            )
+            .unwrap();
+
+        let config = load_config(temp_file.path()).unwrap();
+        assert!(is_temporary(Path::new("movie.mp4.crdownload"), &config));
+        assert!(is_temporary(Path::new("~$report.docx"), &config));
+        assert!(get_extensions_from_config(Path::new("setup.iso.part"), &config).is_empty());
+        assert!(!get_extensions_from_config(Path::new("setup.iso"), &config).is_empty());
+
+        // Firefox's placeholder waits for the `.part` file to be renamed over it.
+        let download_dir = tempfile::TempDir::new().unwrap();
+        let placeholder = download_dir.path().join("report.pdf");
+        let part = download_dir.path().join("report.pdf.part");
+        fs::write(&placeholder, "").unwrap();
+        fs::write(&part, "partial").unwrap();
+        assert!(has_temporary_sibling(&placeholder, &config));
+        assert!(get_extensions_from_config(&placeholder, &config).is_empty());
+
+        fs::rename(&part, &placeholder).unwrap();
+        assert!(!has_temporary_sibling(&placeholder, &config));
+        assert!(!get_extensions_from_config(&placeholder, &config).is_empty());
+    }
+
+    #[test]
+    fn test_apply_actions() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("report.pdf");
+        let copy = temp_dir.path().join("copy.pdf");
+        let moved = temp_dir.path().join("moved.pdf");
+        fs::write(&source, "content").unwrap();
+
+        let rule = crate::FileExtension::default();
+        let planned = |action, target: Option<&PathBuf>| PlannedAction {
+            rule: &rule,
+            action,
+            target: target.cloned(),
+        };
+        let summary = |done: Vec<PlannedAction>| -> Vec<(RuleAction, Option<PathBuf>)> {
+            done.into_iter()
+                .map(|done| (done.action, done.target))
+                .collect()
+        };
+
+        let done = apply_actions(
+            &source,
+            &[
+                planned(RuleAction::Symlink, Some(&moved)),
+                planned(RuleAction::Copy, Some(&copy)),
+                planned(RuleAction::Delete, None),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            summary(done),
+            vec![
+                (RuleAction::Copy, Some(copy.clone())),
+                (RuleAction::Symlink, Some(moved.clone())),
+            ]
+        );
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "content");
+        assert_eq!(fs::read_link(&source).unwrap(), moved);
+
+        let other = temp_dir.path().join("other.pdf");
+        fs::write(&other, "content").unwrap();
+        let done = apply_actions(&other, &[planned(RuleAction::Delete, None)]).unwrap();
+        assert_eq!(summary(done), vec![(RuleAction::Delete, None)]);
+        assert!(!other.exists());
+
+        // A failing action still reports the copies made before it.
+        let third = temp_dir.path().join("third.pdf");
+        let third_copy = temp_dir.path().join("third-copy.pdf");
+        let unreachable = copy.join("third.pdf");
+        fs::write(&third, "content").unwrap();
+        let error = apply_actions(
+            &third,
+            &[
+                planned(RuleAction::Copy, Some(&third_copy)),
+                planned(RuleAction::Move, Some(&unreachable)),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            summary(error.done),
+            vec![(RuleAction::Copy, Some(third_copy.clone()))]
+        );
+        assert!(third.exists() && third_copy.exists());
+    }
+
+    #[test]
+    fn test_route_file_before_hook_vetoes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("report.pdf");
+        let target = temp_dir.path().join("moved.pdf");
+        fs::write(&source, "content").unwrap();
+
+        let mut rule = crate::FileExtension {
+            run_before: Some("test {destination} != {source}".to_string()),
+            run_timeout: DEFAULT_HOOK_TIMEOUT,
+            ..Default::default()
+        };
+        let planned = PlannedAction {
+            rule: &rule,
+            action: RuleAction::Copy,
+            target: Some(source.clone()),
+        };
+        assert!(route_file(&source, vec![planned], "hash")
+            .unwrap()
+            .is_empty());
+
+        // A vetoed action leaves no destination directory behind.
+        let invoices = temp_dir.path().join("Invoices");
+        let vetoed = crate::FileExtension {
+            path: invoices.display().to_string(),
+            run_before: Some("false".to_string()),
+            run_timeout: DEFAULT_HOOK_TIMEOUT,
+            ..Default::default()
+        };
+        let planned = plan_actions(&source, &[&vetoed], None, "hash");
+        assert_eq!(planned.len(), 1);
+        assert!(route_file(&source, planned, "hash").unwrap().is_empty());
+        assert!(!invoices.exists());
+
+        rule.run_after = Some("rm {destination}".to_string());
+        let planned = PlannedAction {
+            rule: &rule,
+            action: RuleAction::Move,
+            target: Some(target.clone()),
+        };
+        assert_eq!(route_file(&source, vec![planned], "hash").unwrap().len(), 1);
+        assert!(!source.exists());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_get_extension_from_config_with_text_content() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let invoice = temp_dir.path().join("scan.md");
+        fs::write(&invoice, "# INVOICE\n\nInvoice no. 2024-0042").unwrap();
+        let notes = temp_dir.path().join("notes.md");
+        fs::write(&notes, "Shopping list").unwrap();
+
+        let extensions = vec![crate::FileExtension {
+            name: Some("md".to_string()),
+            path: "Finance/Invoices".to_string(),
+            text_contains: vec!["invoice".to_string(), "Rechnung".to_string()],
+            text_regex: Some(Regex::new(r"no\. \d{4}-\d+").unwrap()),
+            text_scan_limit: DEFAULT_SCAN_LIMIT,
+            ..Default::default()
+        }];
+        assert!(get_extension_from_config(&invoice, None, &extensions).is_some());
+        assert!(get_extension_from_config(&notes, None, &extensions).is_none());
+    }
+
+    #[test]
+    fn test_load_config_condition_errors() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(
+                br#"
+[directories]
+downloads = "/data/Downloads"
+downloads_enabled = true
+
+[[extensions]]
+when = "ext in [jpg, png] and size > 5XB"
+path = "/data/Photos"
+enabled = true
+"#,
+            )
+            .unwrap();
+
+        let error = load_config(temp_file.path()).unwrap_err().to_string();
+        assert!(
+            error.contains("at column 30: invalid size '5XB'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_load_config_error_locations() {
+        let load_error = |content: &str| {
+            let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+            temp_file.write_all(content.as_bytes()).unwrap();
+            let error = load_config(temp_file.path()).unwrap_err().to_string();
+            let location = format!("Config Error: {}:", temp_file.path().display());
+            assert!(error.starts_with(&location), "{}", error);
+            error[location.len()..].to_string()
+        };
+
+        assert!(load_error(
+            "[directories]\n\n[[extensions]]\nname = \"pdf\"\nglob = \"[invoice\"\npath = \"PDF\"\nenabled = true\n"
+        )
+        .starts_with("5:8: glob: Invalid pattern '[invoice'"));
+        assert!(
+            load_error("[[extensions]]\nname = \"pdf\"\nenabled = true\n")
+                .starts_with("1:1: extensions: Rule with action move needs a path")
+        );
+        assert_eq!(
+            load_error(
+                "[directories]\ndownloads = \"/data\"\n\n[[extensions]]\nname = \"pdf\"\npath = \"PDF\"\nsources = [\"downloads\", \"scans\"]\nenabled = true\n"
+            ),
+            "7:25: sources: Unknown source directory scans"
+        );
+    }
+
+    #[test]
+    fn test_load_config_includes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let conf_d = temp_dir.path().join("conf.d");
+        fs::create_dir(&conf_d).unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+include = ["conf.d/*.toml"]
+
+[directories]
+downloads = "/data/Downloads"
+
+[[extensions]]
+rule = "team-pdf"
+name = "pdf"
+path = "/data/PDF"
+enabled = true
+"#,
+        )
+        .unwrap();
+        fs::write(
+            conf_d.join("20-me.toml"),
+            "[directories]\ndownloads_enabled = true\n\n[[extensions]]\nrule = \"my-pdf\"\nname = \"pdf\"\npath = \"/me/PDF\"\nenabled = true\n",
+        )
+        .unwrap();
+        fs::write(
+            conf_d.join("10-base.toml"),
+            "[directories]\ndownloads = \"/base/Downloads\"\n",
+        )
+        .unwrap();
+
+        let config = load_config(&config_path).unwrap();
+        assert_eq!(config.directories["downloads"], "/base/Downloads");
+        let rules: Vec<&str> = config.extensions.iter().map(|e| e.rule_name()).collect();
+        assert_eq!(rules, vec!["team-pdf", "my-pdf"]);
+        assert!(is_included_config(&conf_d.join("30-new.toml"), &config));
+        assert!(!is_included_config(&conf_d.join("notes.txt"), &config));
+        assert!(is_config_backup(
+            &conf_d.join(".20-team.toml.swp"),
+            &config_path,
+            &config
+        ));
+        assert!(is_config_backup(
+            &temp_dir.path().join("config.toml~"),
+            &config_path,
+            &config
+        ));
+        assert!(!is_config_backup(
+            &conf_d.join("report.pdf"),
+            &config_path,
+            &config
+        ));
+        assert!(!is_config_backup(
+            &conf_d.join("notes.txt~"),
+            &config_path,
+            &config
+        ));
+
+        fs::write(conf_d.join("30-bad.toml"), "match_mode = \"all\"\n").unwrap();
+        let error = load_config(&config_path).unwrap_err().to_string();
+        assert!(
+            error.contains("30-bad.toml:1:1: match_mode: unknown field `match_mode`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_get_fallback_rule() {
+        let watched_dir = tempfile::TempDir::new().unwrap();
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            r#"
+[directories]
+downloads = "{}"
+downloads_enabled = true
+downloads_unsorted = "/data/Unsorted/{{ext}}"
+downloads_unsorted_after = "1h"
+
+[[extensions]]
+name = "pdf"
+path = "/data/PDF"
+enabled = true
+"#,
+            watched_dir.path().display()
+        )
+        .unwrap();
+
+        let mut config = load_config(temp_file.path()).unwrap();
+        let unknown = watched_dir.path().join("data.xyz");
+        fs::write(&unknown, "content").unwrap();
+        fs::write(watched_dir.path().join(".hidden"), "content").unwrap();
+        assert!(get_fallback_rule(&unknown, &config).is_none());
+
+        config
+            .fallback_rules
+            .get_mut("downloads")
+            .unwrap()
+            .older_than = Some(Duration::ZERO);
+        let fallback = get_fallback_rule(&unknown, &config).unwrap();
+        assert_eq!(fallback.rule_name(), "unsorted");
+        assert_eq!(fallback.path, "/data/Unsorted/{ext}");
+        assert!(get_fallback_rule(&watched_dir.path().join(".hidden"), &config).is_none());
+        assert!(get_fallback_rule(Path::new("/elsewhere/data.xyz"), &config).is_none());
+
+        // Folders are as old as their files, but only files are routed.
+        let folder = watched_dir.path().join("old_project");
+        fs::create_dir(&folder).unwrap();
+        assert!(get_fallback_rule(&folder, &config).is_none());
+        let (rules, unmatched) = get_rules_with_fallback(&folder, &config);
+        assert!(rules.is_empty() && !unmatched);
+    }
+
+    #[test]
+    fn test_became_old_between() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let modified = fs::metadata(temp_file.path()).unwrap().modified().unwrap();
+        let rule = |older_than| crate::FileExtension {
+            path: "/data/Archive".to_string(),
+            older_than,
+            ..Default::default()
+        };
+        let minute = rule(Some(Duration::from_secs(60)));
+        let at = |seconds| modified + Duration::from_secs(seconds);
+        let aged = |rule: &crate::FileExtension, since, until| {
+            became_old_between(temp_file.path(), rule, since, until)
+        };
+
+        // The file is a minute old at `modified + 60s`, only the sweep covering that counts it.
+        assert!(aged(&minute, at(30), at(90)));
+        assert!(aged(&minute, at(0), at(60)));
+        assert!(!aged(&minute, at(0), at(30)), "not old yet");
+        assert!(!aged(&minute, at(60), at(120)), "old already");
+        assert!(!aged(&rule(None), at(0), at(90)));
+        assert!(!aged(&rule(Some(Duration::MAX)), at(0), at(90)));
+    }
+
+    #[test]
+    fn test_load_config_quiet_periods() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(
+                br#"
+quiet_period = "10s"
+
+[directories]
+downloads = "/data/Downloads"
+downloads_enabled = true
+downloads_quiet_period = "2m"
+scans = "/data/Scans"
+scans_enabled = true
+
+[[extensions]]
+name = "pdf"
+path = "PDF"
+enabled = true
+"#,
+            )
+            .unwrap();
+
+        let config = load_config(temp_file.path()).unwrap();
+        assert_eq!(
+            quiet_period_for(Path::new("/data/Downloads/a.iso"), &config),
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            quiet_period_for(Path::new("/data/Scans/b.pdf"), &config),
+            Duration::from_secs(10)
+        );
+    }
 }
@@ -0,0 +1,230 @@
+use std::io::Read;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a hook may run when the rule doesn't set `run_timeout`.
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How long to wait for the output of a hook that was killed on timeout.
+const KILLED_OUTPUT_WAIT: Duration = Duration::from_secs(1);
+
+// What a hook command is told about the routed file.
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    pub source: &'a Path,
+    pub destination: Option<&'a Path>,
+    pub rule: &'a str,
+    pub sha256_hash: &'a str,
+    pub action: &'a str,
+}
+
+impl HookContext<'_> {
+    fn values(&self) -> [(&'static str, &'static str, String); 5] {
+        [
+            ("source", "FROUTER_SOURCE", path_value(Some(self.source))),
+            (
+                "destination",
+                "FROUTER_DESTINATION",
+                path_value(self.destination),
+            ),
+            ("rule", "FROUTER_RULE", self.rule.to_string()),
+            ("hash", "FROUTER_HASH", self.sha256_hash.to_string()),
+            ("action", "FROUTER_ACTION", self.action.to_string()),
+        ]
+    }
+}
+
+/*
+Run a hook through `sh -c`. `{source}`, `{destination}`, `{rule}`, `{hash}` and `{action}` are
+replaced with shell-quoted values, which are also exported as `FROUTER_SOURCE` and so on. Fails with
+the exit status and captured output when the command exits non-zero. Past the timeout the command
+is killed along with everything it started, and fails with the output it wrote so far.
+*/
+pub fn run_hook(command: &str, context: &HookContext, timeout: Duration) -> Result<(), String> {
+    let mut rendered = command.to_string();
+    let mut process = Command::new("sh");
+    for (placeholder, variable, value) in context.values() {
+        rendered = rendered.replace(&format!("{{{}}}", placeholder), &shell_quote(&value));
+        process.env(variable, value);
+    }
+    // A process group of its own, so a timeout kills the commands the shell started as well.
+    #[cfg(unix)]
+    process.process_group(0);
+
+    let mut child = process
+        .arg("-c")
+        .arg(&rendered)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run '{}': {}", rendered, e))?;
+
+    // Drain the pipes while waiting, a chatty command would block on a full pipe otherwise.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let status = match wait_with_timeout(&mut child, timeout) {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            return Err(format!(
+                "'{}' timed out after {:?}\nstdout:\n{}\nstderr:\n{}",
+                rendered,
+                timeout,
+                output_after_kill(stdout),
+                output_after_kill(stderr)
+            ))
+        }
+        Err(e) => return Err(format!("Failed to wait for '{}': {}", rendered, e)),
+    };
+    if status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "'{}' exited with {}\nstdout:\n{}\nstderr:\n{}",
+        rendered,
+        status,
+        stdout.join().unwrap_or_default(),
+        stderr.join().unwrap_or_default()
+    ))
+}
+
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            kill_process_group(child)?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) -> std::io::Result<()> {
+    // The hook's process group has the id of the shell that leads it.
+    if unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) -> std::io::Result<()> {
+    child.kill()
+}
+
+/*
+Collect the output of a killed hook. A command that left the process group can still hold the pipe
+open, so its output is only waited for briefly.
+*/
+fn output_after_kill(reader: thread::JoinHandle<String>) -> String {
+    let started = Instant::now();
+    while !reader.is_finished() && started.elapsed() < KILLED_OUTPUT_WAIT {
+        thread::sleep(Duration::from_millis(20));
+    }
+    if reader.is_finished() {
+        reader.join().unwrap_or_default()
+    } else {
+        String::new()
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        String::from_utf8_lossy(&output).to_string()
+    })
+}
+
+fn path_value(path: Option<&Path>) -> String {
+    path.map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(source: &'a Path, destination: &'a Path) -> HookContext<'a> {
+        HookContext {
+            source,
+            destination: Some(destination),
+            rule: "invoices",
+            sha256_hash: "abc123",
+            action: "move",
+        }
+    }
+
+    #[test]
+    fn test_run_hook_placeholders_and_environment() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("it's here.pdf");
+        let destination = temp_dir.path().join("Invoices").join("it's here.pdf");
+        let output = temp_dir.path().join("output.txt");
+
+        let command = format!(
+            "printf '%s|%s|%s' {{source}} \"$FROUTER_RULE\" \"$FROUTER_HASH\" > {}",
+            output.display()
+        );
+        run_hook(
+            &command,
+            &context(&source, &destination),
+            DEFAULT_HOOK_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{}|invoices|abc123", source.display())
+        );
+    }
+
+    #[test]
+    fn test_run_hook_failure_and_timeout() {
+        let source = Path::new("/tmp/a.pdf");
+        let destination = Path::new("/tmp/b/a.pdf");
+
+        let error = run_hook(
+            "echo nope >&2; exit 3",
+            &context(source, destination),
+            DEFAULT_HOOK_TIMEOUT,
+        )
+        .unwrap_err();
+        assert!(error.contains("nope"));
+
+        // The background command is killed with the shell, and the output so far is kept.
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let marker = temp_dir.path().join("still-running");
+        let error = run_hook(
+            &format!(
+                "echo started; (sleep 0.5; touch {}) & wait",
+                marker.display()
+            ),
+            &context(source, destination),
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(error.contains("started"), "{}", error);
+        thread::sleep(Duration::from_millis(800));
+        assert!(!marker.exists());
+    }
+}
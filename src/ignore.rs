@@ -0,0 +1,131 @@
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::Path;
+
+/*
+Name of the per-directory ignore file. It uses the same pattern syntax as `exclude` in the config.
+*/
+pub const IGNORE_FILE_NAME: &str = ".frouterignore";
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+}
+
+/*
+Gitignore-style exclude patterns. Blank lines and `#` comments are skipped, a leading `!`
+re-includes a previously excluded name, a trailing `/` only matches directories and a leading `/`
+is accepted for anchoring. The last matching pattern decides.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreRules {
+    pub fn parse<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Result<Self, String> {
+        let mut patterns = Vec::new();
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let line = line.trim_start_matches('/');
+
+            let pattern = Pattern::new(line)
+                .map_err(|e| format!("Invalid exclude pattern '{}': {}", line, e))?;
+            patterns.push(IgnorePattern {
+                pattern,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Self { patterns })
+    }
+
+    // Append patterns that take precedence over the current ones.
+    pub fn extend(&mut self, other: &IgnoreRules) {
+        self.patterns.extend(other.patterns.iter().cloned());
+    }
+
+    // The literal suffixes of `*.part` style patterns, e.g. `.part`.
+    pub fn suffixes(&self) -> Vec<&str> {
+        self.patterns
+            .iter()
+            .filter(|ignore| !ignore.negated && !ignore.dir_only)
+            .filter_map(|ignore| ignore.pattern.as_str().strip_prefix('*'))
+            .filter(|suffix| !suffix.is_empty() && !suffix.contains(['*', '?', '[']))
+            .collect()
+    }
+
+    pub fn is_ignored(&self, file_name: &str, is_dir: bool) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.patterns
+            .iter()
+            .rev()
+            .find(|ignore| {
+                (!ignore.dir_only || is_dir) && ignore.pattern.matches_with(file_name, options)
+            })
+            .is_some_and(|ignore| !ignore.negated)
+    }
+}
+
+// Read the `.frouterignore` file of a watched directory, if there is one.
+pub fn load_ignore_file(dir: &Path) -> Result<IgnoreRules, String> {
+    match fs::read_to_string(dir.join(IGNORE_FILE_NAME)) {
+        Ok(content) => IgnoreRules::parse(content.lines())
+            .map_err(|e| format!("{} in {}", e, dir.join(IGNORE_FILE_NAME).display())),
+        Err(_) => Ok(IgnoreRules::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let rules = IgnoreRules::parse(
+            "# hidden files\n.*\n!.keep-me\n*.log\nbuild/\n/secret.txt\n"
+                .lines()
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        assert!(rules.is_ignored(".DS_Store", false));
+        assert!(!rules.is_ignored(".keep-me", false));
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(rules.is_ignored("secret.txt", false));
+        assert!(rules.is_ignored("build", true));
+        assert!(!rules.is_ignored("build", false));
+        assert!(!rules.is_ignored("report.pdf", false));
+        assert_eq!(rules.suffixes(), vec![".log"]);
+    }
+
+    #[test]
+    fn test_load_ignore_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert!(!load_ignore_file(temp_dir.path())
+            .unwrap()
+            .is_ignored("a.pdf", false));
+
+        fs::write(temp_dir.path().join(IGNORE_FILE_NAME), "*.pdf\n").unwrap();
+        assert!(load_ignore_file(temp_dir.path())
+            .unwrap()
+            .is_ignored("a.pdf", false));
+    }
+}
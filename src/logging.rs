@@ -1,8 +1,10 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fs::{self, OpenOptions};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use crate::db_utils::Logger;
+use crate::RuleAction;
 
 #[derive(Debug, Serialize)]
 struct ErrorLog {
@@ -28,6 +30,18 @@ pub struct FileEventLog {
     file_name: PathBuf,
     timestamp: String,
     filehash: String,
+    // Missing in logs written before rule actions existed.
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    origin_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct UnmatchedLog {
+    source_path: PathBuf,
+    extension: String,
+    timestamp: String,
 }
 
 /*
@@ -38,6 +52,7 @@ const LOG_TO_JSON: bool = false;
 // Assume these paths are obtained from a configuration file or environment variables
 static ERROR_LOG_PATH: &str = "/usr/local/var/logs/error.log";
 static FILE_EVENT_LOG_PATH: &str = "/usr/local/var/logs/file_event_log.json";
+static UNMATCHED_LOG_PATH: &str = "/usr/local/var/logs/unmatched_log.json";
 
 pub fn log_error_to_file(error_type: &str, message: &str) -> std::io::Result<()> {
     let log = ErrorLog::new(error_type, message);
@@ -50,8 +65,16 @@ pub fn log_error_to_file(error_type: &str, message: &str) -> std::io::Result<()>
     Ok(())
 }
 
-pub fn log_file_event(source_path: &Path, target_path: &Path, filehash: &str) {
+// Record what was done with a file. Deleted files have no target path.
+pub fn log_file_event(
+    source_path: &Path,
+    target_path: Option<&Path>,
+    filehash: &str,
+    action: RuleAction,
+    origin_url: Option<&str>,
+) {
     let current_time = format!("{}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+    let target_path = target_path.unwrap_or(Path::new(""));
 
     if LOG_TO_JSON {
         let log = FileEventLog {
@@ -60,6 +83,8 @@ pub fn log_file_event(source_path: &Path, target_path: &Path, filehash: &str) {
             file_name: source_path.file_name().unwrap().into(),
             timestamp: current_time.clone(),
             filehash: filehash.to_string(),
+            action: action.as_str().to_string(),
+            origin_url: origin_url.map(|url| url.to_string()),
         };
 
         // Use FILE_EVENT_LOG_PATH instead of hard-coded path
@@ -74,19 +99,83 @@ pub fn log_file_event(source_path: &Path, target_path: &Path, filehash: &str) {
             source_path.file_name().unwrap().to_str().unwrap(),
             &current_time,
             filehash,
+            action.as_str(),
+            origin_url,
         ) {
             eprintln!("Failed to insert log without commit: {}", e);
         }
     }
 }
 
-fn append_log_to_json<P: AsRef<Path>>(path: P, log: &FileEventLog) -> std::io::Result<()> {
-    let mut logs = if path.as_ref().exists() {
+/*
+Record a file no rule matched, so missing rules show up in the database. Scans pass the logger
+they already have open, otherwise the database is opened here and failing to is only logged.
+*/
+pub fn log_unmatched_file(logger: Option<&Logger>, path: &Path) {
+    let current_time = format!("{}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if LOG_TO_JSON {
+        let log = UnmatchedLog {
+            source_path: path.to_path_buf(),
+            extension,
+            timestamp: current_time,
+        };
+        if let Err(e) = append_unmatched_to_json(UNMATCHED_LOG_PATH, &log) {
+            eprintln!("Failed to append unmatched file to JSON: {}", e);
+        }
+        return;
+    }
+
+    let opened;
+    let logger = match logger {
+        Some(logger) => logger,
+        None => match Logger::try_new() {
+            Ok(logger) => {
+                opened = logger;
+                &opened
+            }
+            Err(e) => {
+                let _ = log_error_to_file(
+                    "Database Error",
+                    &format!("Failed to open database: {}", e),
+                );
+                return;
+            }
+        },
+    };
+    if let Err(e) = logger.insert_unmatched(&path.to_string_lossy(), &extension, &current_time) {
+        eprintln!("Failed to record unmatched file: {}", e);
+    }
+}
+
+fn read_json_logs<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> std::io::Result<Vec<T>> {
+    if path.as_ref().exists() {
         let file_content = fs::read_to_string(&path)?;
-        serde_json::from_str::<Vec<FileEventLog>>(&file_content).unwrap_or_else(|_| vec![])
+        Ok(serde_json::from_str::<Vec<T>>(&file_content).unwrap_or_else(|_| vec![]))
     } else {
-        vec![]
-    };
+        Ok(vec![])
+    }
+}
+
+// Files are checked again on every rescan, so only the first sighting of a path is kept.
+fn append_unmatched_to_json<P: AsRef<Path>>(path: P, log: &UnmatchedLog) -> std::io::Result<()> {
+    let mut logs: Vec<UnmatchedLog> = read_json_logs(&path)?;
+    if logs.iter().any(|logged| logged.source_path == log.source_path) {
+        return Ok(());
+    }
+
+    logs.push(log.clone());
+    fs::write(path, serde_json::to_string_pretty(&logs)?)?;
+
+    Ok(())
+}
+
+fn append_log_to_json<P: AsRef<Path>>(path: P, log: &FileEventLog) -> std::io::Result<()> {
+    let mut logs: Vec<FileEventLog> = read_json_logs(&path)?;
 
     logs.push(log.clone());
 
@@ -1,15 +1,31 @@
 use crate::logging::{log_error_to_file, log_file_event};
 use hash_compute::compute_sha256;
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
-use std::fs;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{self, RecvError};
+use std::sync::mpsc::{self, RecvTimeoutError};
 
 
+mod archive;
+mod condition;
+mod config_file;
+mod content_type;
+mod exif_reader;
 mod file_utils;
 pub mod hash_compute;
+mod hooks;
+mod ignore;
 mod logging;
+mod origin;
+mod path_expand;
+mod path_template;
+mod preflight;
+mod rename;
+mod stability;
+mod text_content;
+mod trash;
 
 use file_utils::*;
 
@@ -19,114 +35,193 @@ use error::FileRouterError;
 use std::error::Error;
 
 mod db_utils;
-mod test_config;
 // db_utils.rs
 
 use db_utils::Logger;
 
-use std::collections::HashSet;
-use std::time::{Duration, Instant};
+use stability::StabilityTracker;
+use std::time::{Duration, Instant, SystemTime};
 
- /*
-Automatically implement `fmt::Debug` for this struct.
-*/
-#[derive(Debug)]
-struct RecentlyProcessed {
-    files: HashSet<String>,
-    timestamps: HashMap<String, Instant>,
+#[derive(Debug, Default)]
+pub struct Configuration {
+    directories: HashMap<String, String>,
+    extensions: Vec<FileExtension>,
+    match_mode: MatchMode,
+    excludes: ignore::IgnoreRules,
+    directory_excludes: HashMap<String, ignore::IgnoreRules>,
+    temporary_files: ignore::IgnoreRules,
+    quiet_period: Duration,
+    directory_quiet_periods: HashMap<String, Duration>,
+    // Catch-all rules for files no other rule matched, keyed by watched directory.
+    fallback_rules: HashMap<String, FileExtension>,
+    // Patterns of the included config files, a change to a matching file reloads the config.
+    includes: Vec<glob::Pattern>,
 }
 
-// Default implementation for RecentlyProcessed struct to keep track of recently processed files.
-impl RecentlyProcessed {
-    const DEBOUNCE_DURATION: Duration = Duration::from_secs(10);
-
-    /*
-    Create a new RecentlyProcessed struct.
-    */
-    fn new() -> Self {
-        Self {
-            files: HashSet::new(),
-            timestamps: HashMap::new(),
-        }
-    }
-    
-    /*
-    Add a file to the set of recently processed files.
-    */
-    fn add(&mut self, filename: &str) {
-        self.files.insert(filename.to_string());
-        self.timestamps.insert(filename.to_string(), Instant::now());
-    }
+/*
+How many rules a file is routed by. Rules are tried by descending `priority`, then in file order.
+With `First` the file goes to the first matching rule only, with `All` it is copied to every
+matching destination and removed from the source once all copies succeeded.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    #[default]
+    First,
+    All,
+}
 
-    /*
-    Remove old files from the set of recently processed files.
-    */
-    fn remove_old(&mut self) {
-        let old_files: Vec<_> = self
-            .timestamps
-            .iter()
-            .filter(|(_, &v)| v.elapsed() > Self::DEBOUNCE_DURATION)
-            .map(|(k, _)| k.clone())
-            .collect();
-
-        for file in old_files {
-            self.files.remove(&file);
-            self.timestamps.remove(&file);
-        }
-    }
+#[derive(Debug, Default)]
+pub struct FileExtension {
+    rule: Option<String>,
+    priority: i64,
+    sources: Vec<String>,
+    name: Option<String>,
+    path: String,
+    glob: Option<glob::Pattern>,
+    regex: Option<regex::Regex>,
+    condition: Option<condition::Condition>,
+    no_extension: bool,
+    mime: Option<String>,
+    fix_extension: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    older_than: Option<Duration>,
+    newer_than: Option<Duration>,
+    timestamp: TimestampKind,
+    camera: Option<glob::Pattern>,
+    archive_type: Option<String>,
+    archive_contains: Option<glob::Pattern>,
+    archive_limits: archive::ArchiveLimits,
+    text_contains: Vec<String>,
+    text_regex: Option<regex::Regex>,
+    text_scan_limit: u64,
+    origin_host: Option<String>,
+    origin_url: Option<glob::Pattern>,
+    action: RuleAction,
+    run_before: Option<String>,
+    run_after: Option<String>,
+    run_timeout: Duration,
+    rename: Option<String>,
+    strip_copy_suffix: bool,
+    slugify: bool,
+    lowercase_extension: bool,
+    normalize_unicode: bool,
+}
 
-    /*
-    Check if a file is in the set of recently processed files.
-    */
-    fn contains(&self, filename: &str) -> bool {
-        self.files.contains(filename)
+impl FileExtension {
+    // Name used to refer to the rule in messages, falling back to its extension or destination.
+    pub fn rule_name(&self) -> &str {
+        self.rule
+            .as_deref()
+            .or(self.name.as_deref())
+            .unwrap_or(&self.path)
     }
 }
 
-impl Default for RecentlyProcessed {
-    fn default() -> Self {
-        Self::new()
-    }
+/*
+Which file timestamp the `older_than` and `newer_than` conditions are checked against.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum TimestampKind {
+    #[default]
+    #[serde(rename = "mtime")]
+    Modified,
+    #[serde(rename = "ctime")]
+    Changed,
+    #[serde(rename = "atime")]
+    Accessed,
 }
 
-#[derive(Debug)]
-pub struct Configuration {
-    directories: HashMap<String, String>,
-    extensions: Vec<FileExtension>,
+/*
+What a rule does with a matching file. `symlink` moves the file and leaves a link to it in its
+place, `trash` moves it to the user's trash and `ignore` leaves it where it is.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    #[default]
+    Move,
+    Copy,
+    Hardlink,
+    Symlink,
+    Delete,
+    Trash,
+    Ignore,
 }
 
-#[derive(Debug)]
-pub struct FileExtension {
-    name: String,
-    path: String,
+impl RuleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleAction::Move => "move",
+            RuleAction::Copy => "copy",
+            RuleAction::Hardlink => "hardlink",
+            RuleAction::Symlink => "symlink",
+            RuleAction::Delete => "delete",
+            RuleAction::Trash => "trash",
+            RuleAction::Ignore => "ignore",
+        }
+    }
+
+    // Check if the action puts the file under the rule's `path`.
+    pub fn has_destination(&self) -> bool {
+        matches!(
+            self,
+            RuleAction::Move | RuleAction::Copy | RuleAction::Hardlink | RuleAction::Symlink
+        )
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut recently_processed = RecentlyProcessed::new();
+    let mut stability_tracker = StabilityTracker::new();
 
-    let home_config_path = get_home_config_path()?;
+    let args = parse_args(std::env::args().skip(1))?;
+    let (config_path, explicit) = get_config_path(args.config)?;
+
+    // `frouter check` validates the config and exits instead of watching.
+    if args.check {
+        let report = preflight::check_config(&config_path);
+        std::process::exit(preflight::report(&config_path, &report));
+    }
 
     /*
-    Ensure config file exists
+    Ensure config file exists. A config that was asked for by path is never created.
     */
-    ensure_config_exists(&home_config_path);
+    if !explicit {
+        ensure_config_exists(&config_path);
+    } else if !config_path.exists() {
+        return Err(Box::new(FileRouterError::ConfigError(format!(
+            "Config file {} not found",
+            config_path.display()
+        ))));
+    }
 
-    let mut config = load_config(&home_config_path)?;
+    let mut config = load_config(&config_path)?;
 
     /*
     Process existing files in directories
     */
-    process_observed_directory(&config);
+    let mut swept_until = SystemTime::now();
+    for path in process_observed_directory(&config, Scan::All) {
+        stability_tracker.track(&path);
+    }
 
     println!("{:?}", config); // Print the parsed configuration.
 
     let (tx, rx) = mpsc::channel();
 
-    // Ensure directories exist
+    // Ensure directories exist, templated destinations are created per file when routing.
     let directories_to_ensure: Vec<_> = config
         .directories
         .values()
-        .chain(config.extensions.iter().map(|e| &e.path))
+        .chain(
+            config
+                .extensions
+                .iter()
+                .filter(|e| e.action.has_destination())
+                .map(|e| &e.path),
+        )
+        .filter(|dir| !path_template::is_template(dir))
         .collect();
     for dir in directories_to_ensure {
         if let Err(e) = ensure_directory_exists(dir) {
@@ -156,36 +251,38 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Watch directories
     setup_directory_watchers(&config, &mut watcher, &mut watched_dirs);
     watcher
-        .watch(&home_config_path, RecursiveMode::NonRecursive)
+        .watch(&config_path, RecursiveMode::NonRecursive)
         .unwrap();
+    let mut watched_config_dirs: Vec<PathBuf> = Vec::new();
+    setup_config_watchers(&config, &mut watcher, &mut watched_config_dirs);
 
-    // Use a Vec or any other collection to store detected file paths
-    let mut accumulated_events: Vec<PathBuf> = Vec::new();
-    let delay_duration = Duration::from_secs(10); // Adjust as necessary
-    let mut last_event_time = Instant::now();
+    // Changed files are checked for stability this often.
+    let poll_interval = Duration::from_secs(1);
+    let sweep_interval = Duration::from_secs(60);
+    let mut last_sweep_time = Instant::now();
 
     // Process events received from the watcher channel and handle errors.
     loop {
-        match rx.recv() {
-            Ok(Ok(Event { paths, .. })) => {
-                let event_path = &paths[0];
-
-                if !recently_processed.contains(event_path.to_str().unwrap()) {
-                    accumulated_events.push(event_path.clone());
-                    recently_processed.add(event_path.to_str().unwrap());
-                }
-                recently_processed.remove_old();
-
-                last_event_time = Instant::now();
-
-                // Check if the changed file is the config file
-                if event_path == &home_config_path {
-                    process_observed_directory(&config);
+        match rx.recv_timeout(poll_interval) {
+            Ok(Ok(Event { kind, paths, .. })) => {
+                // Renames report the old and the new name, the new one is the file to route.
+                let event_path = match paths.last() {
+                    Some(event_path) => event_path,
+                    None => continue,
+                };
+
+                // Check if the changed file is the config file or one it includes
+                if event_path == &config_path || is_included_config(event_path, &config) {
+                    for path in process_observed_directory(&config, Scan::All) {
+                        stability_tracker.track(&path);
+                    }
                     println!("Config file changed. Reloading...");
-                    match load_config(&home_config_path) {
+                    match load_config(&config_path) {
                         Ok(new_config) => {
                             config = new_config;
                             println!("Config reloaded successfully.");
+                            // New `older_than` rules apply to files that are old already.
+                            swept_until = SystemTime::UNIX_EPOCH;
 
                             // Unwatch previous directories.
                             for dir in &watched_dirs {
@@ -200,39 +297,79 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                             // Watch new directories.
                             setup_directory_watchers(&config, &mut watcher, &mut watched_dirs);
+
+                            for dir in &watched_config_dirs {
+                                let _ = watcher.unwatch(dir);
+                            }
+                            watched_config_dirs.clear();
+                            setup_config_watchers(&config, &mut watcher, &mut watched_config_dirs);
                         }
                         Err(e) => {
-                            let _ = log_error_to_file("Config Load Error", &format!("{:?}", e));
+                            let _ = log_error_to_file("Config Load Error", &e.to_string());
                         }
                     }
-                } else {
+                } else if is_config_backup(event_path, &config_path, &config) {
+                    // Editor backups of the config files stay put, other files next to them don't.
+                } else if is_temporary(event_path, &config) {
+                    // Partly written downloads are picked up once renamed to their final name.
+                } else if is_rename_to(&kind) {
+                    // A download renamed to its final name is complete, so route it right away.
                     handle_directory_event(event_path, &config);
+                } else {
+                    stability_tracker.track(event_path);
                 }
             }
             Ok(Err(e)) => {
                 log_error_to_file("Watch Error", &format!("{:?}", e))?;
             }
-            Err(RecvError) => {
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
                 log_error_to_file("Watch Error", "Failed to receive event")?;
             }
         }
-        // Check if the delay duration has elapsed and process the accumulated events.
-        if last_event_time.elapsed() > delay_duration && !accumulated_events.is_empty() {
-            for path in &accumulated_events {
-                handle_directory_event(path, &config);
+        // Files become old without any event, so `older_than` rules need a periodic re-scan.
+        if last_sweep_time.elapsed() > sweep_interval
+            && (config.extensions.iter().any(|e| e.older_than.is_some())
+                || !config.fallback_rules.is_empty())
+        {
+            let now = SystemTime::now();
+            let scan = Scan::Aged {
+                since: swept_until,
+                until: now,
+            };
+            for path in process_observed_directory(&config, scan) {
+                stability_tracker.track(&path);
+            }
+            swept_until = now;
+            last_sweep_time = Instant::now();
+        }
+        // Route the changed files that stopped changing.
+        if !stability_tracker.is_empty() {
+            for path in stability_tracker.take_stable(|path| quiet_period_for(path, &config)) {
+                handle_directory_event(&path, &config);
             }
-            accumulated_events.clear();
         }
     }
 }
 
-// Process existing files in a directory and log them.
-fn process_observed_directory(config: &Configuration) {
+// Check if an event renamed a file to its current name.
+fn is_rename_to(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Modify(ModifyKind::Name(
+            RenameMode::To | RenameMode::Both | RenameMode::Any
+        ))
+    )
+}
+
+// Process existing files in a directory and log them. Returns the files still being written.
+fn process_observed_directory(config: &Configuration, scan: Scan) -> Vec<PathBuf> {
+    let mut in_progress = Vec::new();
     let logger = Logger::new();
     match logger.start_transaction() {
         Ok(_) => {
             for dir in config.directories.values() {
-                process_existing_files_in_dir(dir, config);
+                in_progress.extend(process_existing_files_in_dir(dir, config, scan, Some(&logger)));
             }
             if let Err(e) = logger.end_transaction() {
                 eprintln!("Failed to commit transaction: {}", e);
@@ -242,6 +379,7 @@ fn process_observed_directory(config: &Configuration) {
             eprintln!("Failed to start transaction: {}", e);
         }
     }
+    in_progress
 }
 
 // Watch a directory for changes and process them.
@@ -263,36 +401,219 @@ fn setup_directory_watchers(
     }
 }
 
+// Watch the directories of included config files, so new and changed fragments reload the config.
+fn setup_config_watchers(
+    config: &Configuration,
+    watcher: &mut RecommendedWatcher,
+    watched_config_dirs: &mut Vec<PathBuf>,
+) {
+    for pattern in &config.includes {
+        let dir = config_file::include_dir(pattern);
+        if watched_config_dirs.contains(&dir) {
+            continue;
+        }
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            let _ = log_error_to_file(
+                "Directory Watch Error",
+                &format!("Failed to watch config directory {}: {}", dir.display(), e),
+            );
+        } else {
+            watched_config_dirs.push(dir);
+        }
+    }
+}
+
 // Handle a directory event by moving the file to the appropriate directory.
 fn handle_directory_event(path: &Path, config: &Configuration) {
-    if path.exists() {
-        if let Some(extension) = get_extension_from_config(path, &config.extensions) {
-            let target_dir = Path::new(&extension.path);
-            let target = target_dir.join(path.file_name().unwrap());
-            let sha256_hash = compute_sha256(path).expect("Failed to compute SHA256 hash");
-            log_file_event(path, &target, &sha256_hash); // Modified function call
-
-            if !target_dir.exists() {
-                fs::create_dir_all(target_dir)
-                    .expect("Failed to create directory for file extension");
-            }
+    // Folders moved into a watched directory are left as they are.
+    if path.is_file() {
+        let (extensions, unmatched) = get_rules_with_fallback(path, config);
+        if unmatched {
+            logging::log_unmatched_file(None, path);
+        }
+        if extensions.is_empty() {
+            return;
+        }
 
-            if let Err(e) = copy_then_delete(path, target) {
+        let sha256_hash = match compute_sha256(path) {
+            Ok(hash) => hash,
+            Err(e) => {
                 let _ = log_error_to_file(
-                    "File Move Error",
-                    &format!("Failed to move file. Error: {}", e),
+                    "Hash Error",
+                    &format!("Failed to compute SHA256 hash for {}: {}", path.display(), e),
                 );
+                return;
             }
+        };
+        let source = source_directory_key(path, config);
+        let planned = plan_actions(path, &extensions, source, &sha256_hash);
+        // Copies don't carry extended attributes, so read the origin before routing.
+        let origin = origin::read_origin(path).and_then(|origin| origin.url);
+
+        // Actions done before one failed are logged too, so the log matches the filesystem.
+        let (done, error) = match route_file(path, planned, &sha256_hash) {
+            Ok(done) => (done, None),
+            Err(e) => (e.done, Some(e.error)),
+        };
+        for done in &done {
+            log_file_event(
+                path,
+                done.target.as_deref(),
+                &sha256_hash,
+                done.action,
+                origin.as_deref(),
+            );
+        }
+        if let Some(e) = error {
+            let _ = log_error_to_file(
+                "File Move Error",
+                &format!("Failed to route {}. Error: {}", path.display(), e),
+            );
+        }
+    }
+}
+
+/*
+Command line options: `frouter [--config <path>] [check [<path>]]`. `check` takes the config to
+validate as an optional argument too.
+*/
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    config: Option<PathBuf>,
+    check: bool,
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, FileRouterError> {
+    let usage = |problem: String| {
+        FileRouterError::ConfigError(format!(
+            "{}\nUsage: frouter [--config <path>] [check [<path>]]",
+            problem
+        ))
+    };
+
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" || arg == "-c" {
+            let path = args
+                .next()
+                .ok_or_else(|| usage(format!("{} needs a path", arg)))?;
+            parsed.config = Some(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            parsed.config = Some(PathBuf::from(path));
+        } else if arg == "check" && !parsed.check {
+            parsed.check = true;
+        } else if parsed.check && parsed.config.is_none() && !arg.starts_with('-') {
+            parsed.config = Some(PathBuf::from(arg));
+        } else {
+            return Err(usage(format!("Unknown argument {}", arg)));
         }
     }
+    Ok(parsed)
 }
 
-// Get the extension of a file from the configuration file.
-fn get_home_config_path() -> Result<PathBuf, FileRouterError> {
-    let home_dir = dirs::home_dir()
-        .ok_or_else(|| FileRouterError::ConfigError("Failed to fetch home directory".into()))?;
-    Ok(home_dir
-        .join(".config")
-        .join("frouter")
-        .join("config.toml"))
+/*
+Find the config file: `--config`, then the `FROUTER_CONFIG` environment variable, then
+`frouter/config.toml` under `XDG_CONFIG_HOME` or `~/.config`. Also returns whether the path was
+given explicitly, rather than being the default location.
+*/
+fn get_config_path(cli_path: Option<PathBuf>) -> Result<(PathBuf, bool), FileRouterError> {
+    let (path, explicit) = resolve_config_path(
+        cli_path,
+        std::env::var_os("FROUTER_CONFIG"),
+        std::env::var_os("XDG_CONFIG_HOME"),
+        dirs::home_dir(),
+    )?;
+    // Watcher events carry absolute paths, so a relative path would never match them.
+    Ok((std::path::absolute(&path).unwrap_or(path), explicit))
+}
+
+fn resolve_config_path(
+    cli_path: Option<PathBuf>,
+    frouter_config: Option<OsString>,
+    xdg_config_home: Option<OsString>,
+    home_dir: Option<PathBuf>,
+) -> Result<(PathBuf, bool), FileRouterError> {
+    if let Some(path) = cli_path.or_else(|| {
+        frouter_config
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }) {
+        return Ok((path, true));
+    }
+
+    // Relative values are invalid per the XDG base directory spec and are ignored.
+    let config_home = match xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+    {
+        Some(config_home) => config_home,
+        None => home_dir
+            .ok_or_else(|| FileRouterError::ConfigError("Failed to fetch home directory".into()))?
+            .join(".config"),
+    };
+    Ok((config_home.join("frouter").join("config.toml"), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, FileRouterError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(&[]).unwrap(), Args::default());
+        assert_eq!(
+            args(&["--config", "share.toml"]).unwrap().config,
+            Some(PathBuf::from("share.toml"))
+        );
+        let check = args(&["check", "--config=team.toml"]).unwrap();
+        assert!(check.check);
+        assert_eq!(check.config, Some(PathBuf::from("team.toml")));
+        assert_eq!(
+            args(&["check", "team.toml"]).unwrap().config,
+            Some(PathBuf::from("team.toml"))
+        );
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["team.toml"]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_config_path() {
+        let home = Some(PathBuf::from("/home/ana"));
+        let resolve = |cli: Option<&str>, env: Option<&str>, xdg: Option<&str>| {
+            resolve_config_path(
+                cli.map(PathBuf::from),
+                env.map(OsString::from),
+                xdg.map(OsString::from),
+                home.clone(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            resolve(None, None, None),
+            (PathBuf::from("/home/ana/.config/frouter/config.toml"), false)
+        );
+        assert_eq!(
+            resolve(None, None, Some("/xdg")),
+            (PathBuf::from("/xdg/frouter/config.toml"), false)
+        );
+        assert_eq!(
+            resolve(None, None, Some("relative")),
+            (PathBuf::from("/home/ana/.config/frouter/config.toml"), false)
+        );
+        assert_eq!(
+            resolve(None, Some("/srv/a.toml"), Some("/xdg")),
+            (PathBuf::from("/srv/a.toml"), true)
+        );
+        assert_eq!(
+            resolve(Some("b.toml"), Some("/srv/a.toml"), None),
+            (PathBuf::from("b.toml"), true)
+        );
+        assert!(!resolve(None, Some(""), None).1);
+    }
 }
@@ -0,0 +1,129 @@
+use std::path::Path;
+
+const ORIGIN_URL_ATTRIBUTE: &str = "user.xdg.origin.url";
+const REFERRER_URL_ATTRIBUTE: &str = "user.xdg.referrer.url";
+
+/*
+Where a download came from, as recorded by browsers in extended attributes. The origin is the URL of
+the file itself, often a CDN, and the referrer the page it was downloaded from.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadOrigin {
+    pub url: Option<String>,
+    pub referrer: Option<String>,
+}
+
+impl DownloadOrigin {
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.url
+            .iter()
+            .chain(self.referrer.iter())
+            .map(|url| url.as_str())
+    }
+
+    // Check if the origin or referrer is on a host or one of its subdomains.
+    pub fn matches_host(&self, host: &str) -> bool {
+        self.urls().filter_map(url_host).any(|url_host| {
+            url_host.eq_ignore_ascii_case(host)
+                || url_host
+                    .len()
+                    .checked_sub(host.len() + 1)
+                    .and_then(|dot| url_host.get(dot..))
+                    .is_some_and(|suffix| {
+                        suffix.starts_with('.') && suffix[1..].eq_ignore_ascii_case(host)
+                    })
+        })
+    }
+}
+
+// Read the download origin of a file. Returns None when neither attribute is set.
+#[cfg(unix)]
+pub fn read_origin<P: AsRef<Path>>(path: P) -> Option<DownloadOrigin> {
+    let attribute = |name: &str| {
+        xattr::get(path.as_ref(), name)
+            .ok()
+            .flatten()
+            .map(|value| String::from_utf8_lossy(&value).trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let origin = DownloadOrigin {
+        url: attribute(ORIGIN_URL_ATTRIBUTE),
+        referrer: attribute(REFERRER_URL_ATTRIBUTE),
+    };
+    if origin == DownloadOrigin::default() {
+        None
+    } else {
+        Some(origin)
+    }
+}
+
+#[cfg(not(unix))]
+pub fn read_origin<P: AsRef<Path>>(_path: P) -> Option<DownloadOrigin> {
+    None
+}
+
+// Extract the host from a URL such as `https://user@github.com:443/owner/repo`.
+pub fn url_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_and_port = authority.rsplit('@').next()?;
+    let host = match host_and_port.strip_prefix('[') {
+        // IPv6 addresses are written in brackets.
+        Some(bracketed) => bracketed.split(']').next()?,
+        None => host_and_port.split(':').next()?,
+    };
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://github.com/owner/repo/releases"),
+            Some("github.com")
+        );
+        assert_eq!(
+            url_host("https://user:pw@example.org:8443?q=1"),
+            Some("example.org")
+        );
+        assert_eq!(url_host("http://[::1]:8080/file"), Some("::1"));
+        assert_eq!(url_host("not a url"), None);
+    }
+
+    #[test]
+    fn test_matches_host() {
+        let origin = DownloadOrigin {
+            url: Some("https://objects.githubusercontent.com/asset".to_string()),
+            referrer: Some("https://github.com/owner/repo".to_string()),
+        };
+        assert!(origin.matches_host("github.com"));
+        assert!(origin.matches_host("GitHubUserContent.com"));
+        assert!(!origin.matches_host("hub.com"));
+        assert!(!origin.matches_host("gitlab.com"));
+    }
+
+    #[test]
+    fn test_read_origin() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("release.tar.gz");
+        std::fs::write(&path, "content").unwrap();
+        assert_eq!(read_origin(&path), None);
+
+        // Not every file system supports user attributes.
+        if xattr::set(&path, ORIGIN_URL_ATTRIBUTE, b"https://github.com/a/b").is_err() {
+            return;
+        }
+        assert_eq!(
+            read_origin(&path).and_then(|origin| origin.url),
+            Some("https://github.com/a/b".to_string())
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Name of the file the XDG user directories are configured in, under `$XDG_CONFIG_HOME`.
+const USER_DIRS_FILE_NAME: &str = "user-dirs.dirs";
+
+/*
+Expand a configured path: a leading `~`, `$VAR`, `${VAR}`, `${VAR:-default}` and XDG user
+directories such as `{xdg:download}`. `$$` is a literal `$`. Other `{...}` placeholders are left for
+the destination templates.
+*/
+pub fn expand_path(path: &str) -> Result<String, String> {
+    expand(path, &|name| env::var(name).ok(), &xdg_user_dir)
+}
+
+fn expand(
+    path: &str,
+    var: &dyn Fn(&str) -> Option<String>,
+    user_dir: &dyn Fn(&str) -> Option<PathBuf>,
+) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(without_tilde) = rest.strip_prefix('~') {
+        let home = dirs::home_dir().ok_or("No home directory to expand ~ with")?;
+        expanded.push_str(&home.to_string_lossy());
+        if !without_tilde.is_empty() {
+            expanded.push('/');
+        }
+        rest = without_tilde.trim_start_matches('/');
+    }
+
+    while let Some(index) = rest.find(['$', '{']) {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("{xdg:") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("Unclosed {{xdg:...}} in {}", path))?;
+            let name = &after[..end];
+            let dir = user_dir(name)
+                .ok_or_else(|| format!("Unknown XDG user directory {{xdg:{}}}", name))?;
+            expanded.push_str(&dir.to_string_lossy());
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("Unclosed ${{...}} in {}", path))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            let value = var(name)
+                .filter(|value| !value.is_empty() || default.is_none())
+                .or_else(|| default.map(str::to_string))
+                .ok_or_else(|| format!("Environment variable {} is not set", name))?;
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('$') {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if end == 0 {
+                expanded.push('$');
+            } else {
+                let name = &after[..end];
+                let value =
+                    var(name).ok_or_else(|| format!("Environment variable {} is not set", name))?;
+                expanded.push_str(&value);
+            }
+            rest = &after[end..];
+        } else {
+            // A template placeholder, kept as written.
+            expanded.push('{');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/*
+Look up an XDG user directory such as `download` or `pictures` in `user-dirs.dirs`, which holds
+the localized or relocated folders. Platforms without the file get the `dirs` crate's defaults.
+*/
+fn xdg_user_dir(name: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let configured = fs::read_to_string(config_dir.join(USER_DIRS_FILE_NAME))
+        .ok()
+        .and_then(|content| parse_user_dirs(&content, &home.to_string_lossy(), name));
+    configured.or_else(|| match name {
+        "desktop" => dirs::desktop_dir(),
+        "download" => dirs::download_dir(),
+        "documents" => dirs::document_dir(),
+        "music" => dirs::audio_dir(),
+        "pictures" => dirs::picture_dir(),
+        "videos" => dirs::video_dir(),
+        "templates" => dirs::template_dir(),
+        "publicshare" => dirs::public_dir(),
+        _ => None,
+    })
+}
+
+// Find `XDG_<NAME>_DIR="$HOME/..."` in the contents of `user-dirs.dirs`.
+fn parse_user_dirs(content: &str, home: &str, name: &str) -> Option<PathBuf> {
+    let key = format!("XDG_{}_DIR", name.to_uppercase());
+    content.lines().find_map(|line| {
+        let (line_key, value) = line.trim().split_once('=')?;
+        if line_key.trim() != key {
+            return None;
+        }
+        let value = value.trim().trim_matches('"');
+        // The file only uses `$HOME/...` or absolute paths.
+        match value.strip_prefix("$HOME") {
+            Some(relative) => Some(PathBuf::from(format!("{}{}", home, relative))),
+            None if value.starts_with('/') => Some(PathBuf::from(value)),
+            None => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_variables() {
+        let var = |name: &str| match name {
+            "HOME" => Some("/home/ana".to_string()),
+            "SHARE" => Some("/mnt/share".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let user_dir =
+            |name: &str| (name == "download").then(|| PathBuf::from("/home/ana/Téléchargements"));
+        let expand = |path: &str| expand(path, &var, &user_dir);
+
+        assert_eq!(expand("$HOME/PDF").unwrap(), "/home/ana/PDF");
+        assert_eq!(
+            expand("${SHARE}/Scans/{ext}").unwrap(),
+            "/mnt/share/Scans/{ext}"
+        );
+        assert_eq!(
+            expand("${PROJECT:-/srv/project}/in").unwrap(),
+            "/srv/project/in"
+        );
+        assert_eq!(expand("${EMPTY:-fallback}").unwrap(), "fallback");
+        assert_eq!(
+            expand("{xdg:download}/{year}").unwrap(),
+            "/home/ana/Téléchargements/{year}"
+        );
+        assert_eq!(expand("/data/$$cash$").unwrap(), "/data/$cash$");
+        assert_eq!(
+            expand("$PROJECT/in").unwrap_err(),
+            "Environment variable PROJECT is not set"
+        );
+        assert_eq!(
+            expand("{xdg:attic}").unwrap_err(),
+            "Unknown XDG user directory {xdg:attic}"
+        );
+    }
+
+    #[test]
+    fn test_parse_user_dirs() {
+        let content = "# Written by xdg-user-dirs-update\nXDG_DOWNLOAD_DIR=\"$HOME/Téléchargements\"\nXDG_PICTURES_DIR=\"/srv/photos\"\n";
+        assert_eq!(
+            parse_user_dirs(content, "/home/ana", "download"),
+            Some(PathBuf::from("/home/ana/Téléchargements"))
+        );
+        assert_eq!(
+            parse_user_dirs(content, "/home/ana", "pictures"),
+            Some(PathBuf::from("/srv/photos"))
+        );
+        assert_eq!(parse_user_dirs(content, "/home/ana", "music"), None);
+    }
+}
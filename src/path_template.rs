@@ -0,0 +1,251 @@
+use crate::exif_reader::ExifData;
+use chrono::{DateTime, Local, NaiveDateTime};
+
+/*
+Placeholders understood in destination paths, e.g. `~/Archive/{ext}/{year}/{month}`.
+`{hash}` takes an optional length, `{hash:12}`, and defaults to 8 characters.
+The `exif.*` dates fall back to the file date when a photo has no EXIF capture date.
+*/
+const PLACEHOLDERS: [&str; 16] = [
+    "year",
+    "month",
+    "day",
+    "date",
+    "name",
+    "stem",
+    "ext",
+    "source",
+    "size_bucket",
+    "hash",
+    "exif.year",
+    "exif.month",
+    "exif.day",
+    "exif.date",
+    "exif.make",
+    "exif.camera",
+];
+
+// Values a destination template is rendered with for a single file.
+#[derive(Debug, Clone)]
+pub struct TemplateContext {
+    pub date: DateTime<Local>,
+    pub stem: String,
+    pub ext: Option<String>,
+    pub source: Option<String>,
+    pub size: u64,
+    pub sha256_hash: String,
+    pub exif: Option<ExifData>,
+}
+
+impl TemplateContext {
+    fn value(&self, placeholder: &str, argument: Option<&str>) -> Option<String> {
+        let value = match placeholder {
+            "year" => self.date.format("%Y").to_string(),
+            "month" => self.date.format("%m").to_string(),
+            "day" => self.date.format("%d").to_string(),
+            "date" => self.date.format("%Y-%m-%d").to_string(),
+            "name" => match &self.ext {
+                Some(ext) => format!("{}.{}", self.stem, ext),
+                None => self.stem.clone(),
+            },
+            "stem" => self.stem.clone(),
+            "ext" => self.ext.clone().unwrap_or_default().to_lowercase(),
+            "source" => self.source.clone().unwrap_or_else(|| "unknown".to_string()),
+            "size_bucket" => size_bucket(self.size).to_string(),
+            "hash" => {
+                let length = argument.and_then(|arg| arg.parse().ok()).unwrap_or(8);
+                self.sha256_hash.chars().take(length).collect()
+            }
+            "exif.year" => self.captured().format("%Y").to_string(),
+            "exif.month" => self.captured().format("%m").to_string(),
+            "exif.day" => self.captured().format("%d").to_string(),
+            "exif.date" => self.captured().format("%Y-%m-%d").to_string(),
+            "exif.make" => self.exif_text(|exif| exif.make.clone()),
+            "exif.camera" => self.exif_text(|exif| exif.model.clone()),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // EXIF capture date, or the file date for files without one.
+    fn captured(&self) -> NaiveDateTime {
+        self.exif
+            .as_ref()
+            .and_then(|exif| exif.captured)
+            .unwrap_or_else(|| self.date.naive_local())
+    }
+
+    fn exif_text(&self, field: impl Fn(&ExifData) -> Option<String>) -> String {
+        self.exif
+            .as_ref()
+            .and_then(field)
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+// Check if a configured path contains any placeholders.
+pub fn is_template(template: &str) -> bool {
+    template.contains('{')
+}
+
+// Check if the last component of a template names the file itself rather than a directory.
+pub fn names_file(template: &str) -> bool {
+    let last_component = template.rsplit('/').next().unwrap_or(template);
+    last_component.contains("{stem}") || last_component.contains("{name}")
+}
+
+// Make sure every placeholder in a template is known, so typos are caught at load time.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for (placeholder, argument) in placeholders(template)? {
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder {{{}}} in {}",
+                placeholder, template
+            ));
+        }
+        if let Some(argument) = argument {
+            if placeholder != "hash" || argument.parse::<usize>().is_err() {
+                return Err(format!(
+                    "Invalid argument '{}' for {{{}}} in {}",
+                    argument, placeholder, template
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Check a `rename` template, which names the file inside its destination and can't add directories.
+pub fn validate_file_name_template(template: &str) -> Result<(), String> {
+    if template.contains(['/', '\\']) {
+        return Err(format!(
+            "Path separator in file name {}, put directories in the rule's path",
+            template
+        ));
+    }
+    validate_template(template)
+}
+
+// Replace every placeholder in a template with the value for this file.
+pub fn render_template(template: &str, context: &TemplateContext) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        rendered.push_str(&rest[..start]);
+
+        let (placeholder, argument) = split_placeholder(&rest[start + 1..end]);
+        match context.value(placeholder, argument) {
+            // Keep rendered values from adding path separators of their own.
+            Some(value) => rendered.push_str(&value.replace('/', "_")),
+            None => rendered.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// Bucket a file size into a coarse label for use in directory names.
+pub fn size_bucket(size: u64) -> &'static str {
+    match size {
+        0..=999_999 => "small",
+        1_000_000..=99_999_999 => "medium",
+        100_000_000..=999_999_999 => "large",
+        _ => "huge",
+    }
+}
+
+fn placeholders(template: &str) -> Result<Vec<(&str, Option<&str>)>, String> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in {}", template))?
+            + start;
+        found.push(split_placeholder(&rest[start + 1..end]));
+        rest = &rest[end + 1..];
+    }
+    Ok(found)
+}
+
+fn split_placeholder(placeholder: &str) -> (&str, Option<&str>) {
+    match placeholder.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (placeholder, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render_template() {
+        let context = TemplateContext {
+            date: Local.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap(),
+            stem: "report".to_string(),
+            ext: Some("PDF".to_string()),
+            source: Some("downloads".to_string()),
+            size: 2_000_000,
+            sha256_hash: "abcdef0123456789".to_string(),
+            exif: None,
+        };
+
+        assert_eq!(
+            render_template("/Archive/{ext}/{year}/{month}/{stem}.{ext}", &context),
+            "/Archive/pdf/2024/04/report.pdf"
+        );
+        assert_eq!(
+            render_template("/{source}/{size_bucket}/{hash:4}-{name}", &context),
+            "/downloads/medium/abcd-report.PDF"
+        );
+    }
+
+    #[test]
+    fn test_render_template_with_exif() {
+        let mut context = TemplateContext {
+            date: Local.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap(),
+            stem: "IMG_0001".to_string(),
+            ext: Some("jpg".to_string()),
+            source: None,
+            size: 0,
+            sha256_hash: String::new(),
+            exif: None,
+        };
+        assert_eq!(
+            render_template("/Photos/{exif.year}/{exif.camera}", &context),
+            "/Photos/2024/unknown"
+        );
+
+        context.exif = Some(ExifData {
+            captured: crate::exif_reader::parse_exif_datetime("2019:12:24 18:00:00"),
+            make: Some("Apple".to_string()),
+            model: Some("iPhone 11".to_string()),
+        });
+        assert_eq!(
+            render_template("/Photos/{exif.year}/{exif.date}/{exif.camera}", &context),
+            "/Photos/2019/2019-12-24/iPhone 11"
+        );
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("~/Archive/{ext}/{date}").is_ok());
+        assert!(names_file("~/Archive/{ext}/{stem}.{ext}"));
+        assert!(!names_file("~/Archive/{stem}/files"));
+        assert!(validate_template("~/Archive/{extension}").is_err());
+        assert!(validate_template("~/Archive/{hash:abc}").is_err());
+        assert!(validate_template("~/Archive/{year").is_err());
+
+        assert!(validate_file_name_template("{date} {stem}.{ext}").is_ok());
+        assert!(validate_file_name_template("{year}/{stem}.{ext}").is_err());
+        assert!(validate_file_name_template(r"scans\{stem}.{ext}").is_err());
+        assert!(validate_file_name_template("{stem}.{extension}").is_err());
+    }
+}
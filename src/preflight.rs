@@ -0,0 +1,369 @@
+use crate::file_utils::{get_extensions_from_config, load_config};
+use crate::path_template::is_template;
+use crate::{Configuration, FileExtension, MatchMode};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The outcome of `check_config`. Notes are worth knowing but don't fail the check.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub problems: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+/*
+Check a config before it is deployed, for `frouter check`. No problems means the config is ready to
+use. Nothing is created, destinations the daemon would create are listed as notes.
+*/
+pub fn check_config(config_path: &Path) -> CheckReport {
+    let config = match load_config(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            return CheckReport {
+                problems: vec![e.to_string()],
+                notes: Vec::new(),
+            }
+        }
+    };
+
+    let mut report = CheckReport {
+        problems: check_watched_directories(&config),
+        notes: Vec::new(),
+    };
+    check_destinations(&config, &mut report);
+    report.problems.extend(check_rule_conflicts(&config));
+    report
+}
+
+// Print the outcome of `check_config` and return the process exit code.
+pub fn report(config_path: &Path, report: &CheckReport) -> i32 {
+    let problems = &report.problems;
+    if problems.is_empty() {
+        println!("{}: OK", config_path.display());
+    } else {
+        println!("{}:", config_path.display());
+    }
+    for problem in problems {
+        println!("  - {}", problem);
+    }
+    for note in &report.notes {
+        println!("  note: {}", note);
+    }
+    if problems.is_empty() {
+        return 0;
+    }
+
+    println!(
+        "{} problem{} found",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    );
+    1
+}
+
+fn check_watched_directories(config: &Configuration) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, dir) in sorted(&config.directories) {
+        if !Path::new(dir).is_dir() {
+            problems.push(format!(
+                "Watched directory {} ({}) does not exist",
+                key, dir
+            ));
+        } else if let Err(e) = fs::read_dir(dir) {
+            problems.push(format!(
+                "Watched directory {} ({}) is not readable: {}",
+                key, dir, e
+            ));
+        }
+    }
+    problems
+}
+
+/*
+Check that every destination can be written to, or created in its nearest existing parent. Nothing
+is created, the write test uses a temporary file that is removed right away. Directories are
+watched without their subdirectories, so a destination is only a problem when files would land
+directly in a watched directory, where they would be routed again.
+*/
+fn check_destinations(config: &Configuration, report: &mut CheckReport) {
+    let rules = config
+        .extensions
+        .iter()
+        .chain(
+            sorted(&config.fallback_rules)
+                .into_iter()
+                .map(|(_, rule)| rule),
+        )
+        .filter(|rule| rule.action.has_destination());
+
+    for rule in rules {
+        // A templated destination depends on the file, only its part before the first placeholder
+        // is known.
+        let destination = static_prefix(&rule.path);
+        if is_template(&rule.path) {
+            report.notes.push(format!(
+                "Destination {} of rule {} depends on the file, only {} was checked",
+                rule.path,
+                rule.rule_name(),
+                destination.display()
+            ));
+        } else if let Some((key, _)) = sorted(&config.directories)
+            .into_iter()
+            .find(|(_, dir)| same_directory(&destination, Path::new(dir)))
+        {
+            report.problems.push(format!(
+                "Rule {} routes files into watched directory {} ({})",
+                rule.rule_name(),
+                key,
+                destination.display()
+            ));
+            continue;
+        }
+
+        let existing = match nearest_existing(&destination) {
+            Some(existing) => existing,
+            None => {
+                report.problems.push(format!(
+                    "Destination {} of rule {} has no existing parent directory",
+                    destination.display(),
+                    rule.rule_name()
+                ));
+                continue;
+            }
+        };
+        if !existing.is_dir() {
+            report.problems.push(format!(
+                "Destination {} of rule {} can't be created: {} is not a directory",
+                destination.display(),
+                rule.rule_name(),
+                existing.display()
+            ));
+            continue;
+        }
+
+        let writable = tempfile::Builder::new()
+            .prefix(".frouter-check")
+            .tempfile_in(&existing)
+            .map(|_| ());
+        match writable {
+            Err(e) if existing == destination => report.problems.push(format!(
+                "Destination {} of rule {} is not writable: {}",
+                destination.display(),
+                rule.rule_name(),
+                e
+            )),
+            Err(e) => report.problems.push(format!(
+                "Destination {} of rule {} can't be created in {}: {}",
+                destination.display(),
+                rule.rule_name(),
+                existing.display(),
+                e
+            )),
+            Ok(()) if existing != destination && !is_template(&rule.path) => {
+                report.notes.push(format!(
+                    "Destination {} of rule {} does not exist and would be created",
+                    destination.display(),
+                    rule.rule_name()
+                ))
+            }
+            Ok(()) => {}
+        }
+    }
+}
+
+/*
+Look up a sample file for every rule that matches on an extension, in each directory the rule
+applies to. With `match_mode = "first"` another rule winning means the rule never applies, with
+`all` a second rule with the same action and destination routes the file twice.
+*/
+fn check_rule_conflicts(config: &Configuration) -> Vec<String> {
+    let mut problems = Vec::new();
+    for rule in &config.extensions {
+        let name = match &rule.name {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let sample_dirs: Vec<&str> = if config.directories.is_empty() {
+            vec![""]
+        } else {
+            sorted(&config.directories)
+                .into_iter()
+                .filter(|(key, _)| rule.sources.is_empty() || rule.sources.contains(key))
+                .map(|(_, dir)| dir.as_str())
+                .collect()
+        };
+        for dir in sample_dirs {
+            let sample = Path::new(dir).join(format!("frouter-check.{}", name));
+            let matched = get_extensions_from_config(&sample, config);
+            if !matched.iter().any(|other| std::ptr::eq(*other, rule)) {
+                // Either another rule wins, or the rule needs more than the extension to match.
+                if let Some(winner) = matched.first().filter(|_| {
+                    config.match_mode == MatchMode::First && matches_extension_only(rule)
+                }) {
+                    problems.push(format!(
+                        "Rule {} never applies to .{} files: rule {} matches them first",
+                        rule.rule_name(),
+                        name,
+                        winner.rule_name()
+                    ));
+                    break;
+                }
+                continue;
+            }
+
+            if let Some(duplicate) = matched
+                .iter()
+                .take_while(|other| !std::ptr::eq(**other, rule))
+                .find(|other| other.action == rule.action && other.path == rule.path)
+            {
+                problems.push(format!(
+                    "Rules {} and {} both {} .{} files to {}",
+                    duplicate.rule_name(),
+                    rule.rule_name(),
+                    rule.action.as_str(),
+                    name,
+                    rule.path
+                ));
+                break;
+            }
+        }
+    }
+    problems
+}
+
+// Check that a rule matches on the extension alone, so it would match the sample file by itself.
+fn matches_extension_only(rule: &FileExtension) -> bool {
+    rule.glob.is_none()
+        && rule.regex.is_none()
+        && rule.condition.is_none()
+        && rule.mime.is_none()
+        && rule.min_size.is_none()
+        && rule.max_size.is_none()
+        && rule.older_than.is_none()
+        && rule.newer_than.is_none()
+        && rule.camera.is_none()
+        && rule.archive_type.is_none()
+        && rule.archive_contains.is_none()
+        && rule.text_contains.is_empty()
+        && rule.text_regex.is_none()
+        && rule.origin_host.is_none()
+        && rule.origin_url.is_none()
+}
+
+// The directories of a destination up to its first placeholder, all of it for a plain path.
+fn static_prefix(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .take_while(|component| !is_template(&component.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+// Find the deepest part of a path that exists, relative paths end at the working directory.
+fn nearest_existing(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.exists())
+        .map(Path::to_path_buf)
+}
+
+fn same_directory(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.components().eq(b.components()),
+    }
+}
+
+// Map entries by key, so the report lists them in a stable order.
+fn sorted<V>(map: &std::collections::HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let downloads = temp_dir.path().join("Downloads");
+        fs::create_dir(&downloads).unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[directories]
+downloads = "{downloads}"
+downloads_enabled = true
+scans = "{root}/Scans"
+scans_enabled = true
+
+[[extensions]]
+rule = "documents"
+name = "pdf"
+path = "{root}/Documents"
+enabled = true
+
+[[extensions]]
+rule = "invoices"
+name = "pdf"
+path = "{root}/Invoices"
+enabled = true
+
+[[extensions]]
+rule = "loop"
+name = "txt"
+path = "{downloads}"
+enabled = true
+
+[[extensions]]
+rule = "photos"
+name = "jpg"
+path = "{root}/Photos/{{year}}"
+enabled = true
+"#,
+                downloads = downloads.display(),
+                root = temp_dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let check = check_config(&config_path);
+        let problems = &check.problems;
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("Watched directory scans"));
+        assert!(problems[1].starts_with("Rule loop routes files into watched directory downloads"));
+        assert_eq!(
+            problems[2],
+            "Rule invoices never applies to .pdf files: rule documents matches them first"
+        );
+        assert_eq!(report(&config_path, &check), 1);
+
+        // Checking doesn't create anything, missing and templated destinations are noted.
+        assert!(!temp_dir.path().join("Invoices").exists());
+        assert_eq!(check.notes.len(), 3, "{:?}", check.notes);
+        assert!(check.notes[0].ends_with("of rule documents does not exist and would be created"));
+        assert!(check.notes[2].contains("of rule photos depends on the file, only"));
+
+        fs::write(
+            &config_path,
+            "[[extensions]]\nname = \"pdf\"\nenabled = true\n",
+        )
+        .unwrap();
+        let check = check_config(&config_path);
+        assert!(
+            check.problems[0].contains("needs a path"),
+            "{:?}",
+            check.problems
+        );
+    }
+}
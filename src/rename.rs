@@ -0,0 +1,70 @@
+use unicode_normalization::UnicodeNormalization;
+
+// Remove a browser duplicate suffix like ` (1)` from the end of a file stem.
+pub fn strip_copy_suffix(stem: &str) -> &str {
+    let without_paren = match stem.strip_suffix(')') {
+        Some(without_paren) => without_paren,
+        None => return stem,
+    };
+    let open = match without_paren.rfind('(') {
+        Some(open) => open,
+        None => return stem,
+    };
+
+    let counter = &without_paren[open + 1..];
+    let base = without_paren[..open].trim_end();
+    if counter.is_empty() || !counter.chars().all(|c| c.is_ascii_digit()) || base.is_empty() {
+        return stem;
+    }
+    base
+}
+
+// Turn spaces and punctuation into single dashes and lowercase the rest, e.g. `My File!` -> `my-file`.
+pub fn slugify(stem: &str) -> String {
+    let mut slug = String::new();
+    let mut pending_dash = false;
+    for c in stem.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    if slug.is_empty() {
+        stem.to_string()
+    } else {
+        slug
+    }
+}
+
+// Compose Unicode characters (NFC), so names written on macOS match what other systems expect.
+pub fn normalize_unicode(name: &str) -> String {
+    name.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_copy_suffix() {
+        assert_eq!(strip_copy_suffix("report (1)"), "report");
+        assert_eq!(strip_copy_suffix("report(12)"), "report");
+        assert_eq!(strip_copy_suffix("report (final)"), "report (final)");
+        assert_eq!(strip_copy_suffix("(1)"), "(1)");
+        assert_eq!(strip_copy_suffix("report"), "report");
+    }
+
+    #[test]
+    fn test_slugify_and_normalize() {
+        assert_eq!(slugify("My Holiday -- Photos!"), "my-holiday-photos");
+        assert_eq!(slugify("Q1_report v2.1"), "q1_report-v2.1");
+        assert_eq!(slugify("???"), "???");
+        assert_eq!(normalize_unicode("Cafe\u{301}"), "Caf\u{e9}");
+    }
+}
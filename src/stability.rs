@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug)]
+struct Observation {
+    size: u64,
+    modified: Option<SystemTime>,
+    stable_since: Instant,
+}
+
+impl Observation {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            stable_since: Instant::now(),
+        })
+    }
+}
+
+/*
+Keeps track of files that changed recently and hands them out once they stopped changing: their
+size and mtime stayed the same for the quiet period and no process has them open for writing.
+*/
+#[derive(Debug, Default)]
+pub struct StabilityTracker {
+    pending: HashMap<PathBuf, Observation>,
+}
+
+impl StabilityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Start or keep watching a file that changed.
+    pub fn track(&mut self, path: &Path) {
+        if self.pending.contains_key(path) {
+            return;
+        }
+        if let Some(observation) = Observation::of(path) {
+            self.pending.insert(path.to_path_buf(), observation);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // Remove and return the files that are ready to be routed.
+    pub fn take_stable<F: Fn(&Path) -> Duration>(&mut self, quiet_period_for: F) -> Vec<PathBuf> {
+        let mut stable = Vec::new();
+        self.pending.retain(|path, observation| {
+            let current = match Observation::of(path) {
+                Some(current) => current,
+                // Gone already, e.g. moved by the user or routed on rename.
+                None => return false,
+            };
+
+            if current.size != observation.size || current.modified != observation.modified {
+                *observation = current;
+                return true;
+            }
+
+            if observation.stable_since.elapsed() < quiet_period_for(path)
+                || is_open_for_writing(path)
+            {
+                return true;
+            }
+
+            stable.push(path.clone());
+            false
+        });
+        stable
+    }
+}
+
+/*
+Check if a file found by a directory scan is done: its mtime is at least the quiet period old and no
+process has it open for writing.
+*/
+pub fn is_settled(path: &Path, quiet_period: Duration) -> bool {
+    let quiet = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age >= quiet_period));
+    quiet && !is_open_for_writing(path)
+}
+
+// Check if any process has the file open for writing by looking through `/proc/*/fd`.
+#[cfg(target_os = "linux")]
+pub fn is_open_for_writing(path: &Path) -> bool {
+    const O_ACCMODE: u32 = 0o3;
+
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let processes = match fs::read_dir("/proc") {
+        Ok(processes) => processes,
+        Err(_) => return false,
+    };
+
+    for process in processes.flatten() {
+        let process_dir = process.path();
+        let fds = match fs::read_dir(process_dir.join("fd")) {
+            Ok(fds) => fds,
+            // Other users' processes can't be inspected without privileges.
+            Err(_) => continue,
+        };
+        for fd in fds.flatten() {
+            if fs::read_link(fd.path()).ok().as_deref() != Some(path.as_path()) {
+                continue;
+            }
+            let fdinfo = process_dir.join("fdinfo").join(fd.file_name());
+            let flags = fs::read_to_string(fdinfo).ok().and_then(|info| {
+                info.lines()
+                    .find_map(|line| line.strip_prefix("flags:"))
+                    .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+            });
+            // O_WRONLY is 1 and O_RDWR is 2.
+            if flags.is_some_and(|flags| flags & O_ACCMODE != 0) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// Other platforms have no cheap way to tell, so only the size and mtime checks apply.
+#[cfg(not(target_os = "linux"))]
+pub fn is_open_for_writing(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_stable_waits_for_quiet_period() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("download.iso");
+        fs::write(&path, "partial").unwrap();
+
+        let mut tracker = StabilityTracker::new();
+        tracker.track(&path);
+        assert!(tracker
+            .take_stable(|_| Duration::from_secs(3600))
+            .is_empty());
+        assert_eq!(tracker.take_stable(|_| Duration::ZERO), vec![path]);
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_take_stable_resets_on_change() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("download.iso");
+        fs::write(&path, "partial").unwrap();
+
+        let mut tracker = StabilityTracker::new();
+        tracker.track(&path);
+        fs::write(&path, "partial and then some more").unwrap();
+        assert!(tracker.take_stable(|_| Duration::ZERO).is_empty());
+        assert_eq!(tracker.take_stable(|_| Duration::ZERO), vec![path]);
+    }
+
+    #[test]
+    fn test_is_settled() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("download.iso");
+        fs::write(&path, "complete").unwrap();
+
+        assert!(!is_settled(&path, Duration::from_secs(3600)));
+        assert!(is_settled(&path, Duration::ZERO));
+        assert!(!is_settled(
+            &temp_dir.path().join("missing.iso"),
+            Duration::ZERO
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_is_open_for_writing() {
+        use std::io::Write;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("download.iso");
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        assert!(is_open_for_writing(&path));
+
+        drop(file);
+        assert!(!is_open_for_writing(&path));
+    }
+}
@@ -0,0 +1,283 @@
+use flate2::read::ZlibDecoder;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// How much of a file is scanned when the rule doesn't set `text_scan_limit`.
+pub const DEFAULT_SCAN_LIMIT: u64 = 1_000_000;
+
+// Extensions of files that are read as plain text.
+const TEXT_EXTENSIONS: [&str; 6] = ["txt", "text", "csv", "tsv", "md", "markdown"];
+
+/*
+Extract the text of a plain text, CSV or Markdown file, or the text shown by a PDF. At most `limit`
+bytes are read from the file, and PDF streams are decompressed up to the same limit. PDFs are not
+OCRed, so scans and PDFs with custom font encodings yield little or no text.
+*/
+pub fn extract_text<P: AsRef<Path>>(path: P, limit: u64) -> Option<String> {
+    let path = path.as_ref();
+    let mut bytes = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(limit)
+        .read_to_end(&mut bytes)
+        .ok()?;
+
+    if bytes.starts_with(b"%PDF-") {
+        return Some(extract_pdf_text(&bytes, limit));
+    }
+
+    let extension = path.extension()?.to_str()?;
+    if TEXT_EXTENSIONS
+        .iter()
+        .any(|text| text.eq_ignore_ascii_case(extension))
+    {
+        Some(String::from_utf8_lossy(&bytes).to_string())
+    } else {
+        None
+    }
+}
+
+// Collect the strings drawn by the text operators of every content stream in a PDF.
+pub fn extract_pdf_text(pdf: &[u8], limit: u64) -> String {
+    let mut text = String::new();
+    let mut rest = pdf;
+    while let Some(start) = find(rest, b"stream") {
+        let dictionary = &rest[..start];
+        let mut data_start = start + b"stream".len();
+        // The keyword is followed by CRLF or LF before the data.
+        if rest.get(data_start) == Some(&b'\r') {
+            data_start += 1;
+        }
+        if rest.get(data_start) == Some(&b'\n') {
+            data_start += 1;
+        }
+        let data_end = match find(&rest[data_start..], b"endstream") {
+            Some(end) => data_start + end,
+            None => break,
+        };
+        let data = &rest[data_start..data_end];
+        rest = &rest[data_end + b"endstream".len()..];
+
+        // Only the dictionary of this stream's object, not the whole preceding document.
+        let dictionary = match rfind(dictionary, b"obj") {
+            Some(object_start) => &dictionary[object_start..],
+            None => dictionary,
+        };
+        let content = if find(dictionary, b"/FlateDecode").is_some() {
+            let mut decoded = Vec::new();
+            if ZlibDecoder::new(data)
+                .take(limit)
+                .read_to_end(&mut decoded)
+                .is_err()
+                && decoded.is_empty()
+            {
+                continue;
+            }
+            decoded
+        } else if find(dictionary, b"/Filter").is_some() {
+            // Images and other encodings don't hold text.
+            continue;
+        } else {
+            data.to_vec()
+        };
+
+        if find(&content, b"BT").is_some() {
+            collect_shown_strings(&content, &mut text);
+        }
+    }
+    text
+}
+
+/*
+Walk a content stream and append the literal and hex strings shown with `Tj`, `TJ`, `'` and `"`.
+Large negative adjustments inside `TJ` arrays are how PDFs space words, so they become spaces.
+*/
+fn collect_shown_strings(content: &[u8], text: &mut String) {
+    let mut index = 0;
+    let mut in_array = false;
+    while index < content.len() {
+        match content[index] {
+            b'(' => {
+                let (string, end) = literal_string(content, index + 1);
+                text.push_str(&string);
+                index = end;
+            }
+            b'<' if content.get(index + 1) != Some(&b'<') => {
+                let end = content[index..]
+                    .iter()
+                    .position(|&byte| byte == b'>')
+                    .map_or(content.len(), |end| index + end);
+                text.push_str(&hex_string(&content[index + 1..end]));
+                index = end + 1;
+            }
+            b'[' => {
+                in_array = true;
+                index += 1;
+            }
+            b']' => {
+                in_array = false;
+                index += 1;
+            }
+            b'-' if in_array => {
+                let end = content[index + 1..]
+                    .iter()
+                    .position(|byte| !(byte.is_ascii_digit() || *byte == b'.'))
+                    .map_or(content.len(), |end| index + 1 + end);
+                let adjustment: f64 = std::str::from_utf8(&content[index..end])
+                    .ok()
+                    .and_then(|number| number.parse().ok())
+                    .unwrap_or(0.0);
+                if adjustment < -200.0 {
+                    text.push(' ');
+                }
+                index = end.max(index + 1);
+            }
+            byte if byte.is_ascii_alphabetic() || byte == b'\'' || byte == b'"' => {
+                let end = content[index..]
+                    .iter()
+                    .position(|byte| !(byte.is_ascii_alphabetic() || *byte == b'*'))
+                    .map_or(content.len(), |end| index + end)
+                    .max(index + 1);
+                let operator = &content[index..end];
+                if matches!(
+                    operator,
+                    b"Tj" | b"TJ" | b"'" | b"\"" | b"Td" | b"TD" | b"T*" | b"ET"
+                ) && !text.ends_with(' ')
+                {
+                    text.push(' ');
+                }
+                index = end;
+            }
+            _ => index += 1,
+        }
+    }
+}
+
+// Decode a literal string starting after its `(`, returning the text and the index after the `)`.
+fn literal_string(content: &[u8], start: usize) -> (String, usize) {
+    let mut bytes = Vec::new();
+    let mut depth = 0;
+    let mut index = start;
+    while index < content.len() {
+        let byte = content[index];
+        index += 1;
+        match byte {
+            b'\\' => {
+                let escaped = match content.get(index) {
+                    Some(escaped) => *escaped,
+                    None => break,
+                };
+                index += 1;
+                match escaped {
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'0'..=b'7' => {
+                        let mut value = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            match content.get(index) {
+                                Some(digit @ b'0'..=b'7') => {
+                                    value = value * 8 + u32::from(digit - b'0');
+                                    index += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                    }
+                    // A backslash at the end of a line continues the string.
+                    b'\r' | b'\n' => {}
+                    other => bytes.push(other),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                bytes.push(byte);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                bytes.push(byte);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    (decode_pdf_string(&bytes), index)
+}
+
+fn hex_string(hex: &[u8]) -> String {
+    let digits: Vec<u8> = hex
+        .iter()
+        .filter_map(|byte| (*byte as char).to_digit(16).map(|digit| digit as u8))
+        .collect();
+    let bytes: Vec<u8> = digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+        .collect();
+    decode_pdf_string(&bytes)
+}
+
+// Strings are UTF-16 when they start with a byte order mark, and close enough to Latin-1 otherwise.
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(b"\xFE\xFF") {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks(2)
+                .map(|pair| u16::from(pair[0]) << 8 | u16::from(pair.get(1).copied().unwrap_or(0)))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn test_extract_pdf_text() {
+        let content = b"BT /F1 12 Tf 72 712 Td (Invoice No. 42) Tj 0 -14 Td [(Total) -250 (\\(EUR\\))] TJ <48656C6C6F> Tj ET";
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut pdf =
+            b"%PDF-1.4\n1 0 obj\n<< /Length 10 /Filter /FlateDecode >>\nstream\n".to_vec();
+        pdf.extend_from_slice(&compressed);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n2 0 obj\n<< /Length 8 /Filter /DCTDecode >>\nstream\n(Hidden) Tj BT\nendstream\n%%EOF");
+
+        let text = extract_pdf_text(&pdf, DEFAULT_SCAN_LIMIT);
+        assert!(text.contains("Invoice No. 42"));
+        assert!(text.contains("Total (EUR)"));
+        assert!(text.contains("Hello"));
+        assert!(!text.contains("Hidden"));
+    }
+
+    #[test]
+    fn test_extract_text_from_plain_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let csv = temp_dir.path().join("statement.CSV");
+        std::fs::write(&csv, "date,amount\n2024-04-01,12.50\n").unwrap();
+        assert_eq!(extract_text(&csv, 10).unwrap(), "date,amoun");
+
+        let binary = temp_dir.path().join("photo.jpg");
+        std::fs::write(&binary, "\u{FF}\u{D8}").unwrap();
+        assert!(extract_text(&binary, DEFAULT_SCAN_LIMIT).is_none());
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Move a file into the user's trash so it can still be restored, and return where it ended up.
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let home = dirs::home_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No home directory"))?;
+    if cfg!(target_os = "macos") {
+        move_into(path, &home.join(".Trash"))
+    } else {
+        let data_dir = dirs::data_dir().unwrap_or_else(|| home.join(".local").join("share"));
+        move_to_freedesktop_trash(path, &data_dir.join("Trash"))
+    }
+}
+
+/*
+The freedesktop.org trash keeps the files in `files/` and a `.trashinfo` file with the original
+location in `info/`, which is what file managers use to restore them.
+*/
+fn move_to_freedesktop_trash(path: &Path, trash_dir: &Path) -> io::Result<PathBuf> {
+    let original = fs::canonicalize(path)?;
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&info_dir)?;
+
+    let trashed = move_into(path, &trash_dir.join("files"))?;
+    let trashed_name = trashed.file_name().unwrap_or_default().to_string_lossy();
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&original.to_string_lossy()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    fs::write(info_dir.join(format!("{}.trashinfo", trashed_name)), info)?;
+    Ok(trashed)
+}
+
+fn move_into(path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+
+    let mut target = dir.join(file_name);
+    let mut counter = 1;
+    while target.exists() {
+        target = dir.join(format!("{} {}", file_name.to_string_lossy(), counter));
+        counter += 1;
+    }
+
+    // The trash usually lives on another file system than the watched directory.
+    if fs::rename(path, &target).is_err() {
+        fs::copy(path, &target)?;
+        fs::remove_file(path)?;
+    }
+    Ok(target)
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_to_freedesktop_trash() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("old report.pdf");
+        fs::write(&path, "content").unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+
+        let trashed = move_to_freedesktop_trash(&path, &trash_dir).unwrap();
+        assert!(!path.exists());
+        assert_eq!(trashed, trash_dir.join("files").join("old report.pdf"));
+
+        let info =
+            fs::read_to_string(trash_dir.join("info").join("old report.pdf.trashinfo")).unwrap();
+        assert!(info.contains("/old%20report.pdf\n"));
+    }
+}